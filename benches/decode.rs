@@ -0,0 +1,35 @@
+use bendy::decoding::FromBencode;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use krpc_message::{fast, raw};
+
+const PACKETS: &[(&str, &[u8])] = &[
+    ("ping", b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe"),
+    (
+        "get_peers",
+        b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234566:noseedi0e6:scrapei1ee1:q9:get_peers1:t2:aa1:y1:qe",
+    ),
+    (
+        "find_node_response",
+        b"d1:rd2:id20:abcdefghij01234567895:nodes208:mnopqrstuvwxyz123456ABCDaamnopqrstuvwxyz123456ABCDaamnopqrstuvwxyz123456ABCDaamnopqrstuvwxyz123456ABCDaamnopqrstuvwxyz123456ABCDaamnopqrstuvwxyz123456ABCDaamnopqrstuvwxyz123456ABCDaamnopqrstuvwxyz123456ABCDaae1:t2:aa1:y1:re",
+    ),
+    (
+        "put",
+        b"d1:ad3:casi3e2:id20:abcdefghij01234567891:k32:kkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkk4:salt6:foobar3:seqi4e3:sig64:ssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssss5:token8:aoeusnth1:vl1:ad1:bi1eeee1:q3:put1:t2:aa1:y1:qe",
+    ),
+];
+
+fn decode(c: &mut Criterion) {
+    for (name, bytes) in PACKETS {
+        let mut group = c.benchmark_group(*name);
+        group.bench_function("bendy", |b| {
+            b.iter(|| raw::Message::from_bencode(black_box(bytes)).unwrap())
+        });
+        group.bench_function("fast", |b| {
+            b.iter(|| fast::decode(black_box(bytes)).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
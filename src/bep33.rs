@@ -0,0 +1,88 @@
+use std::{fmt, net::IpAddr};
+
+use bendy::{
+    decoding::{FromBencode, Object},
+    encoding::{SingleItemEncoder, ToBencode},
+};
+use sha1::{Digest, Sha1};
+
+use crate::error::invalid_length;
+
+const M: usize = 256 * 8;
+const K: f64 = 2.0;
+
+/// BEP 33 bloom filter of seed (`BFsd`) or downloader (`BFpe`) IPs.
+#[derive(PartialEq, Eq, Clone)]
+pub struct ScrapeBloomFilter {
+    pub bytes: Box<[u8; 256]>,
+}
+
+impl ScrapeBloomFilter {
+    pub fn new() -> Self {
+        ScrapeBloomFilter {
+            bytes: Box::new([0; 256]),
+        }
+    }
+
+    pub fn insert(&mut self, ip: IpAddr) {
+        let hash = match ip {
+            IpAddr::V4(ip) => Sha1::digest(ip.octets()),
+            IpAddr::V6(ip) => Sha1::digest(ip.octets()),
+        };
+        let index1 = (hash[0] as usize | (hash[1] as usize) << 8) % M;
+        let index2 = (hash[2] as usize | (hash[3] as usize) << 8) % M;
+        self.bytes[index1 / 8] |= 1 << (index1 % 8);
+        self.bytes[index2 / 8] |= 1 << (index2 % 8);
+    }
+
+    /// Combine filters received from several nodes.
+    pub fn merge(&mut self, other: &ScrapeBloomFilter) {
+        for (a, b) in self.bytes.iter_mut().zip(other.bytes.iter()) {
+            *a |= b;
+        }
+    }
+
+    /// Estimated number of distinct IPs inserted.
+    pub fn estimate(&self) -> f64 {
+        let zeros = M - self
+            .bytes
+            .iter()
+            .map(|b| b.count_ones() as usize)
+            .sum::<usize>();
+        let c = zeros.min(M - 1) as f64;
+        let m = M as f64;
+        (c / m).ln() / (K * (1.0 - 1.0 / m).ln())
+    }
+}
+
+impl Default for ScrapeBloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ScrapeBloomFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.bytes.iter() {
+            write!(f, "{:02x}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromBencode for ScrapeBloomFilter {
+    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        let s = object.try_into_bytes()?;
+        Ok(ScrapeBloomFilter {
+            bytes: Box::new(s.try_into().map_err(|_| invalid_length!(s.len()))?),
+        })
+    }
+}
+
+impl ToBencode for ScrapeBloomFilter {
+    const MAX_DEPTH: usize = 0;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
+        encoder.emit_bytes(&self.bytes[..])
+    }
+}
@@ -0,0 +1,31 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::bep33::ScrapeBloomFilter;
+
+#[test]
+fn test_insert() {
+    // sha1(192.168.0.1) starts with dc272e43, giving bits 2012 and 814
+    let mut bf = ScrapeBloomFilter::new();
+    bf.insert("192.168.0.1".parse().unwrap());
+    let mut expected = [0u8; 256];
+    expected[251] = 0x10;
+    expected[101] = 0x40;
+    assert_eq!(*bf.bytes, expected);
+}
+
+#[test]
+fn test_estimate() {
+    let mut bf = ScrapeBloomFilter::new();
+    assert!(bf.estimate() < 1.0);
+    for i in 0..1024u32 {
+        bf.insert(Ipv4Addr::from(0xc0a80000 + i).into());
+    }
+    for i in 0..1000u128 {
+        bf.insert(Ipv6Addr::from(0x20010db8_u128 << 96 | i).into());
+    }
+    assert!((bf.estimate() - 2024.0).abs() < 2024.0 * 0.05);
+
+    let mut merged = ScrapeBloomFilter::new();
+    merged.merge(&bf);
+    assert_eq!(merged, bf);
+}
@@ -0,0 +1,60 @@
+use std::net::IpAddr;
+
+use crate::raw::{Hash, Node};
+
+const V4_MASK: [u8; 4] = [0x03, 0x0f, 0x3f, 0xff];
+const V6_MASK: [u8; 8] = [0x01, 0x03, 0x07, 0x0f, 0x1f, 0x3f, 0x7f, 0xff];
+
+fn id_prefix(ip: IpAddr, r: u8) -> [u8; 3] {
+    let mut buf = [0u8; 8];
+    let len = match ip {
+        IpAddr::V4(ip) => {
+            for (i, (o, m)) in ip.octets().iter().zip(V4_MASK).enumerate() {
+                buf[i] = o & m;
+            }
+            4
+        }
+        IpAddr::V6(ip) => {
+            for (i, (o, m)) in ip.octets().iter().zip(V6_MASK).enumerate() {
+                buf[i] = o & m;
+            }
+            8
+        }
+    };
+    buf[0] |= (r & 0x07) << 5;
+    let crc = crc32c::crc32c(&buf[..len]).to_be_bytes();
+    [crc[0], crc[1], crc[2] & 0xf8]
+}
+
+/// Local and private addresses are exempt from the node ID restriction.
+fn is_exempt(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_loopback(),
+    }
+}
+
+/// Derive a node ID valid for `ip` from `random`, keeping as many of its bits as possible.
+pub fn generate_id(ip: IpAddr, random: &Hash) -> Hash {
+    let mut bytes = random.bytes;
+    let prefix = id_prefix(ip, bytes[19]);
+    bytes[0] = prefix[0];
+    bytes[1] = prefix[1];
+    bytes[2] = prefix[2] | (bytes[2] & 0x07);
+    Hash { bytes }
+}
+
+/// Check that `id` was derived from the external address `ip`.
+pub fn is_valid_id(id: &Hash, ip: IpAddr) -> bool {
+    if is_exempt(ip) {
+        return true;
+    }
+    let prefix = id_prefix(ip, id.bytes[19]);
+    id.bytes[0] == prefix[0] && id.bytes[1] == prefix[1] && id.bytes[2] & 0xf8 == prefix[2]
+}
+
+impl Node {
+    pub fn has_valid_id(&self) -> bool {
+        is_valid_id(&self.id, self.addr.ip())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{
+    bep42::{generate_id, is_valid_id},
+    raw::{Hash, Node},
+};
+
+fn hex(s: &str) -> Hash {
+    let mut bytes = [0u8; 20];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    Hash { bytes }
+}
+
+// test vectors from BEP 42
+#[test]
+fn test_vectors() {
+    let vectors = [
+        ("124.31.75.21", "5fbfbff10c5d6a4ec8a88e4c6ab4c28b95eee401"),
+        ("21.75.31.124", "5a3ce9c14e7a08645677bbd1cfe7d8f956d53256"),
+        ("65.23.51.170", "a5d43220bc8f112a3d426c84764f8c2a1150e616"),
+        ("84.124.73.14", "1b0321dd1bb1fe518101ceef99462b947a01ff41"),
+        ("43.213.53.83", "e56f6cbf5b7c4be0237986d5243b87aa6d51305a"),
+    ];
+    for (ip, id) in vectors {
+        let ip = ip.parse().unwrap();
+        let id = hex(id);
+        assert!(is_valid_id(&id, ip));
+
+        let mut random = id.clone();
+        random.bytes[0] ^= 0xff;
+        random.bytes[1] ^= 0xff;
+        assert!(!is_valid_id(&random, ip));
+        assert_eq!(generate_id(ip, &random), id);
+    }
+}
+
+#[test]
+fn test_node() {
+    let random = hex("0123456789abcdef0123456789abcdef01234567");
+    let ip = "2001:db8::1".parse().unwrap();
+    let node: Node = (
+        generate_id(ip, &random),
+        "[2001:db8::1]:6881".parse().unwrap(),
+    )
+        .into();
+    assert!(node.has_valid_id());
+
+    let node: Node = (random.clone(), "65.23.51.170:6881".parse().unwrap()).into();
+    assert!(!node.has_valid_id());
+
+    let node: Node = (random, "192.168.1.1:6881".parse().unwrap()).into();
+    assert!(node.has_valid_id());
+}
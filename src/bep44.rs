@@ -0,0 +1,108 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    raw::{Hash, RawValue, Response, TransactionId},
+    Put,
+};
+
+/// Target of an immutable item: SHA1 of the bencoded value.
+pub fn immutable_target(value: &RawValue) -> Hash {
+    Hash {
+        bytes: Sha1::digest(&value.bytes).into(),
+    }
+}
+
+/// Target of a mutable item: SHA1 of the public key followed by the salt.
+pub fn mutable_target(key: &[u8; 32], salt: &[u8]) -> Hash {
+    let mut hasher = Sha1::new();
+    hasher.update(key);
+    hasher.update(salt);
+    Hash {
+        bytes: hasher.finalize().into(),
+    }
+}
+
+/// Buffer covered by a mutable item signature: `4:salt<salt>3:seqi<seq>e1:v<v>`,
+/// the salt part is left out when the salt is empty.
+pub fn signing_buffer(salt: &[u8], seq: i64, value: &RawValue) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(salt.len() + value.bytes.len() + 32);
+    if !salt.is_empty() {
+        buf.extend_from_slice(format!("4:salt{}:", salt.len()).as_bytes());
+        buf.extend_from_slice(salt);
+    }
+    buf.extend_from_slice(format!("3:seqi{}e1:v", seq).as_bytes());
+    buf.extend_from_slice(&value.bytes);
+    buf
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MutableItem {
+    pub key: [u8; 32],
+    pub salt: Vec<u8>,
+    pub seq: i64,
+    pub value: RawValue,
+    pub signature: [u8; 64],
+}
+
+impl MutableItem {
+    pub fn sign(keypair: &SigningKey, salt: &[u8], seq: i64, value: RawValue) -> Self {
+        let signature = keypair.sign(&signing_buffer(salt, seq, &value));
+        MutableItem {
+            key: keypair.verifying_key().to_bytes(),
+            salt: salt.to_vec(),
+            seq,
+            value,
+            signature: signature.to_bytes(),
+        }
+    }
+
+    /// Build an item from a `get` response, the salt is not sent back so it
+    /// has to be supplied by the caller.
+    pub fn from_response(response: &Response, salt: &[u8]) -> Option<Self> {
+        Some(MutableItem {
+            key: response.key?,
+            salt: salt.to_vec(),
+            seq: response.seq?,
+            value: response.value.clone()?,
+            signature: response.signature?,
+        })
+    }
+
+    pub fn verify(&self) -> bool {
+        let Ok(key) = VerifyingKey::from_bytes(&self.key) else {
+            return false;
+        };
+        let signature = Signature::from_bytes(&self.signature);
+        key.verify(
+            &signing_buffer(&self.salt, self.seq, &self.value),
+            &signature,
+        )
+        .is_ok()
+    }
+
+    pub fn target(&self) -> Hash {
+        mutable_target(&self.key, &self.salt)
+    }
+
+    pub fn into_put<I, T>(self, transaction_id: I, sender_id: T, token: Vec<u8>) -> Put
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+    {
+        let put = Put::new_mutable(
+            transaction_id,
+            sender_id,
+            token,
+            self.value,
+            self.key,
+            self.signature,
+            self.seq,
+        );
+        if self.salt.is_empty() {
+            put
+        } else {
+            put.with_salt(self.salt)
+        }
+    }
+}
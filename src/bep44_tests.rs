@@ -0,0 +1,84 @@
+use ed25519_dalek::SigningKey;
+
+use crate::{
+    bep44::{immutable_target, mutable_target, signing_buffer, MutableItem},
+    raw::{Hash, RawValue},
+    Message,
+};
+
+fn hex<const N: usize>(s: &str) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    bytes
+}
+
+fn hello_world() -> RawValue {
+    RawValue {
+        bytes: b"12:Hello World!".to_vec(),
+    }
+}
+
+// test vectors from BEP 44
+#[test]
+fn test_vectors() {
+    let key = hex("77ff84905a91936367c01360803104f92432fcd904a43511876df5cdf3e7e548");
+
+    assert_eq!(
+        signing_buffer(b"", 1, &hello_world()),
+        b"3:seqi1e1:v12:Hello World!"
+    );
+    let item = MutableItem {
+        key,
+        salt: Vec::new(),
+        seq: 1,
+        value: hello_world(),
+        signature: hex("305ac8aeb6c9c151fa120f120ea2cfb923564e11552d06a5d856091e5e853cff1260d3f39e4999684aa92eb73ffd136e6f4f3ecbfda0ce53a1608ecd7ae21f01"),
+    };
+    assert!(item.verify());
+    assert_eq!(
+        item.target(),
+        Hash::from(&hex("4a533d47ec9c7d95b1ad75f576cffc641853b750"))
+    );
+
+    assert_eq!(
+        signing_buffer(b"foobar", 1, &hello_world()),
+        b"4:salt6:foobar3:seqi1e1:v12:Hello World!"
+    );
+    let item = MutableItem {
+        key,
+        salt: b"foobar".to_vec(),
+        seq: 1,
+        value: hello_world(),
+        signature: hex("6834284b6b24c3204eb2fea824d82f88883a3d95e8b4a21b8c0ded553d17d17ddf9a8a7104b1258f30bed3787e6cb896fca78c58f8e03b5f18f14951a87d9a08"),
+    };
+    assert!(item.verify());
+    assert_eq!(
+        mutable_target(&key, b"foobar"),
+        Hash::from(&hex("411eba73b6f087ca51a3795d9c8c938d365e32c1"))
+    );
+
+    assert_eq!(
+        immutable_target(&hello_world()),
+        Hash::from(&hex("e5f96f6f38320f0f33959cb4d3d656452117aadb"))
+    );
+}
+
+#[test]
+fn test_sign() {
+    let keypair = SigningKey::from_bytes(&[7u8; 32]);
+    let mut item = MutableItem::sign(&keypair, b"foobar", 4, hello_world());
+    assert!(item.verify());
+
+    let put = item
+        .clone()
+        .into_put(24929u16, b"abcdefghij0123456789", b"aoeusnth".to_vec());
+    let Message::Put(decoded) = Message::decode(&put.clone().encode().unwrap()).unwrap() else {
+        panic!("expected put");
+    };
+    assert_eq!(decoded, put);
+
+    item.seq = 5;
+    assert!(!item.verify());
+}
@@ -0,0 +1,165 @@
+//! KRPC over a plain [`std::net::UdpSocket`], for tools that don't want an
+//! async runtime.
+//!
+//! A socket either sends queries with [`KrpcSocket::query`] or answers them
+//! with [`KrpcSocket::serve`]. Queries arriving while waiting for a response
+//! are dropped.
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock,
+    transaction::TransactionManager,
+    udp::{is_per_packet, ErrorReplies},
+    Message, QueryError, Response,
+};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_RETRIES: u32 = 2;
+
+pub struct KrpcSocket {
+    socket: UdpSocket,
+    transactions: TransactionManager,
+    buf: Vec<u8>,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl KrpcSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(KrpcSocket {
+            socket: UdpSocket::bind(addr)?,
+            transactions: TransactionManager::new(DEFAULT_TIMEOUT),
+            buf: vec![0; 65536],
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+        })
+    }
+
+    /// How long to wait for a response before retransmitting.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retransmits after the first attempt.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Send `query` to `addr` under a fresh transaction ID and wait for the
+    /// response. A KRPC error reply becomes [`QueryError::Remote`].
+    pub fn query(
+        &mut self,
+        addr: SocketAddr,
+        query: impl Into<Message>,
+    ) -> Result<Response, QueryError> {
+        let mut msg = query.into();
+        let query_type = msg.query_type().ok_or(QueryError::NotAQuery)?;
+        let transaction_id = self.transactions.start_with_timeout(
+            addr,
+            query_type,
+            msg.query_target(),
+            self.timeout.saturating_mul(self.retries.saturating_add(1)),
+        )?;
+        msg.set_transaction_id(transaction_id.clone());
+        let result = self.send_and_wait(addr, &msg);
+        self.transactions.cancel(addr, &transaction_id);
+        result
+    }
+
+    fn send_and_wait(&mut self, addr: SocketAddr, msg: &Message) -> Result<Response, QueryError> {
+        let mut packet = Vec::new();
+        msg.encode_into(&mut packet)?;
+        for _ in 0..=self.retries {
+            self.socket.send_to(&packet, addr)?;
+            let deadline = clock::deadline(Instant::now(), self.timeout);
+            while let Some((reply, from)) = self.recv_until(deadline)? {
+                // queries, unsolicited and spoofed responses are dropped
+                if self.transactions.complete(from, &reply).is_err() {
+                    continue;
+                }
+                return match reply {
+                    Message::Response(response) => Ok(response),
+                    Message::Error(error) => Err(QueryError::Remote(error)),
+                    _ => unreachable!("completed by a query"),
+                };
+            }
+        }
+        Err(QueryError::Timeout)
+    }
+
+    /// The next message that decodes, `None` once `deadline` passes.
+    fn recv_until(&mut self, deadline: Instant) -> io::Result<Option<(Message, SocketAddr)>> {
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            let (len, from) = match self.socket.recv_from(&mut self.buf) {
+                Ok(received) => received,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) if is_per_packet(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Ok(msg) = Message::decode(&self.buf[..len]) {
+                return Ok(Some((msg, from)));
+            }
+        }
+    }
+
+    /// Answer queries with `handler` until the socket fails. The reply, if
+    /// any, is sent with the query's transaction ID. Queries that fail to
+    /// decode are answered with an error, up to a limit per second, responses
+    /// are ignored.
+    pub fn serve<F, R>(&mut self, mut handler: F) -> io::Result<()>
+    where
+        F: FnMut(&Message, SocketAddr) -> Option<R>,
+        R: Into<Message>,
+    {
+        self.socket.set_read_timeout(None)?;
+        let mut error_replies = ErrorReplies::new(Instant::now());
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut self.buf) {
+                Ok(received) => received,
+                Err(e) if is_per_packet(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            let reply = match Message::decode_lenient(&self.buf[..len]) {
+                Ok(msg) if msg.query_type().is_some() => handler(&msg, from).map(|reply| {
+                    let mut reply = reply.into();
+                    reply.set_transaction_id(msg.transaction_id().clone());
+                    reply
+                }),
+                Ok(_) => None,
+                Err(failure) => error_replies
+                    .reply(&failure, Instant::now())
+                    .map(Message::from),
+            };
+            if let Some(reply) = reply {
+                let mut packet = Vec::new();
+                // a reply we can't encode is the handler's bug, not the peer's
+                if reply.encode_into(&mut packet).is_ok() {
+                    let _ = self.socket.send_to(&packet, from);
+                }
+            }
+        }
+    }
+}
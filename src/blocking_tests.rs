@@ -0,0 +1,175 @@
+use std::{net::UdpSocket, thread, time::Duration};
+
+use crate::{
+    blocking::KrpcSocket,
+    raw::{Node, QueryType},
+    response::TypedResponse,
+    AnnouncePeer, ErrorCode, ErrorMessage, FindNode, GetPeers, Message, Ping, QueryError,
+};
+
+const SERVER_ID: &[u8; 20] = b"mnopqrstuvwxyz123456";
+const CLIENT_ID: &[u8; 20] = b"abcdefghij0123456789";
+const INFO_HASH: &[u8; 20] = b"0123456789abcdefghij";
+
+fn bind() -> KrpcSocket {
+    KrpcSocket::bind("127.0.0.1:0").unwrap()
+}
+
+fn raw_socket() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    socket
+}
+
+/// Answers pings, find_node and announce_peer, get_peers with an error and
+/// ignores everything else.
+fn handler(msg: &Message, from: std::net::SocketAddr) -> Option<Message> {
+    Some(match msg {
+        Message::Ping(p) => p.reply(SERVER_ID).into(),
+        Message::FindNode(f) => f
+            .reply(
+                SERVER_ID,
+                vec![Node {
+                    id: SERVER_ID.into(),
+                    addr: from,
+                }],
+            )
+            .into(),
+        Message::AnnouncePeer(a) => a.reply(SERVER_ID).into(),
+        Message::GetPeers(g) => {
+            ErrorMessage::new(g.transaction_id().clone(), ErrorCode::Server, "go away").into()
+        }
+        _ => return None,
+    })
+}
+
+fn spawn_server() -> std::net::SocketAddr {
+    let mut server = bind();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.serve(handler));
+    addr
+}
+
+#[test]
+fn test_query() {
+    let server_addr = spawn_server();
+    let mut client = bind();
+
+    let response = client
+        .query(server_addr, Ping::new(0u16, CLIENT_ID))
+        .unwrap();
+    assert_eq!(response.sender_id, SERVER_ID.into());
+
+    let response = client
+        .query(server_addr, FindNode::new(0u16, CLIENT_ID, SERVER_ID))
+        .unwrap();
+    let TypedResponse::FindNode(r) = response.into_typed(QueryType::FindNone).unwrap() else {
+        panic!("expected a find_node response");
+    };
+    assert_eq!(r.nodes[0].addr, client.local_addr().unwrap());
+
+    let error = client
+        .query(server_addr, GetPeers::new(0u16, CLIENT_ID, INFO_HASH))
+        .unwrap_err();
+    assert!(matches!(error, QueryError::Remote(e) if e.code == ErrorCode::Server));
+
+    let announce = AnnouncePeer::new(0u16, CLIENT_ID, INFO_HASH, 6881, b"token".to_vec());
+    assert!(client.query(server_addr, announce).is_ok());
+
+    assert!(matches!(
+        client.query(server_addr, ErrorMessage::generic(0u16, "")),
+        Err(QueryError::NotAQuery)
+    ));
+}
+
+#[test]
+fn test_timeout() {
+    let peer = raw_socket();
+    let mut client = bind()
+        .with_timeout(Duration::from_millis(50))
+        .with_retries(2);
+
+    let error = client
+        .query(peer.local_addr().unwrap(), Ping::new(0u16, CLIENT_ID))
+        .unwrap_err();
+    assert!(matches!(error, QueryError::Timeout));
+
+    // the same packet went out three times
+    let mut buf = [0; 1500];
+    let mut seen = Vec::new();
+    for _ in 0..3 {
+        let (len, _) = peer.recv_from(&mut buf).unwrap();
+        seen.push(buf[..len].to_vec());
+    }
+    assert!(seen.windows(2).all(|w| w[0] == w[1]));
+}
+
+#[test]
+fn test_huge_retries() {
+    let server_addr = spawn_server();
+    let mut client = bind().with_timeout(Duration::MAX).with_retries(u32::MAX);
+
+    let response = client
+        .query(server_addr, Ping::new(0u16, CLIENT_ID))
+        .unwrap();
+    assert_eq!(response.sender_id, SERVER_ID.into());
+}
+
+#[test]
+fn test_spoofed_response() {
+    let peer = raw_socket();
+    let peer_addr = peer.local_addr().unwrap();
+    let client = thread::spawn(move || bind().query(peer_addr, Ping::new(0u16, CLIENT_ID)));
+
+    let mut buf = [0; 1500];
+    let (len, client_addr) = peer.recv_from(&mut buf).unwrap();
+    let Message::Ping(ping) = Message::decode(&buf[..len]).unwrap() else {
+        panic!("expected a ping");
+    };
+
+    // the right transaction ID from the wrong address is dropped
+    let mut bytes = Vec::new();
+    ping.reply(CLIENT_ID).encode_into(&mut bytes).unwrap();
+    raw_socket().send_to(&bytes, client_addr).unwrap();
+
+    let mut bytes = Vec::new();
+    ping.reply(SERVER_ID).encode_into(&mut bytes).unwrap();
+    peer.send_to(&bytes, client_addr).unwrap();
+
+    let response = client.join().unwrap().unwrap();
+    assert_eq!(response.sender_id, SERVER_ID.into());
+}
+
+#[test]
+fn test_serve_malformed() {
+    let server_addr = spawn_server();
+    let raw = raw_socket();
+    let mut buf = [0; 1500];
+
+    raw.send_to(b"d1:ad2:id3:abce1:q4:ping1:t2:aa1:y1:qe", server_addr)
+        .unwrap();
+    let (len, _) = raw.recv_from(&mut buf).unwrap();
+    let Message::Error(e) = Message::decode(&buf[..len]).unwrap() else {
+        panic!("expected an error reply");
+    };
+    assert_eq!(e.code, ErrorCode::Protocol);
+    assert_eq!(e.transaction_id, b"aa".into());
+
+    // the handler ignores `get`, and responses are never answered
+    let mut bytes = Vec::new();
+    crate::Get::new(b"bb", CLIENT_ID, INFO_HASH)
+        .encode_into(&mut bytes)
+        .unwrap();
+    raw.send_to(&bytes, server_addr).unwrap();
+    let mut bytes = Vec::new();
+    Ping::new(b"cc", CLIENT_ID)
+        .reply(CLIENT_ID)
+        .encode_into(&mut bytes)
+        .unwrap();
+    raw.send_to(&bytes, server_addr).unwrap();
+    raw.set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+    assert!(raw.recv_from(&mut buf).is_err());
+}
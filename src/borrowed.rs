@@ -0,0 +1,497 @@
+//! Zero-copy decoding: every byte string borrows from the input buffer, and
+//! compact lists are only unpacked when iterated.
+
+use std::net::SocketAddr;
+
+use bendy::decoding::{Decoder, FromBencode, Object};
+
+use crate::{
+    error::{invalid_length, invalid_value, missing, ResultExt},
+    raw::{
+        self, ClientVersion, Extra, Hash, MessageType, Node, QueryType, RawValue,
+        ScrapeBloomFilter, SocketAddrWrap, TransactionId, Want,
+    },
+    Error, Message,
+};
+
+/// Compact node infos, 26 bytes each in `nodes` and 38 bytes each in `nodes6`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactNodes<'a> {
+    bytes: &'a [u8],
+    stride: usize,
+}
+
+impl<'a> CompactNodes<'a> {
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len() / self.stride
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Node> + 'a {
+        // the length is a multiple of 26 or 38, checked when decoding
+        self.bytes
+            .chunks_exact(self.stride)
+            .map(|chunk| Node::try_from(chunk).unwrap())
+    }
+}
+
+/// The `values` list of compact peer addresses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactPeers<'a> {
+    /// The bencoded list, every item checked to be 6 or 18 bytes.
+    raw: &'a [u8],
+}
+
+impl<'a> CompactPeers<'a> {
+    pub fn iter(&self) -> CompactPeersIter<'a> {
+        CompactPeersIter {
+            rest: &self.raw[1..self.raw.len() - 1],
+        }
+    }
+}
+
+pub struct CompactPeersIter<'a> {
+    rest: &'a [u8],
+}
+
+impl Iterator for CompactPeersIter<'_> {
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<SocketAddr> {
+        let colon = self.rest.iter().position(|&b| b == b':')?;
+        let len = match &self.rest[..colon] {
+            b"6" => 6,
+            b"18" => 18,
+            _ => return None,
+        };
+        let (item, rest) = self.rest[colon + 1..].split_at(len);
+        self.rest = rest;
+        SocketAddrWrap::try_from(item).ok().map(SocketAddr::from)
+    }
+}
+
+/// Concatenated 20-byte hashes, as in BEP 51 `samples`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactHashes<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> CompactHashes<'a> {
+    pub fn len(&self) -> usize {
+        self.bytes.len() / 20
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8; 20]> + 'a {
+        self.bytes
+            .chunks_exact(20)
+            .map(|chunk| chunk.try_into().unwrap())
+    }
+}
+
+/// The BEP 32 `want` list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WantList<'a> {
+    /// The list items, every one checked to be `2:n4` or `2:n6`.
+    items: &'a [u8],
+}
+
+impl<'a> WantList<'a> {
+    pub fn len(&self) -> usize {
+        self.items.len() / 4
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Want> + 'a {
+        self.items
+            .chunks_exact(4)
+            .map(|item| Want::from_name(&item[2..]).unwrap())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryArgsRef<'a> {
+    pub sender_id: &'a [u8; 20], // id
+    pub target: Option<&'a [u8; 20]>,
+    pub info_hash: Option<&'a [u8; 20]>,
+    pub implied_port: Option<bool>,
+    pub port: Option<u16>,
+    pub token: Option<&'a [u8]>,
+    pub want: Option<WantList<'a>>,
+    /// Full bencoding of `v`.
+    pub value: Option<&'a [u8]>,
+    pub key: Option<&'a [u8; 32]>,       // k
+    pub signature: Option<&'a [u8; 64]>, // sig
+    pub seq: Option<i64>,
+    pub cas: Option<i64>,
+    pub salt: Option<&'a [u8]>,
+    pub scrape: Option<bool>,
+    pub noseed: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseRef<'a> {
+    pub sender_id: &'a [u8; 20], // id
+    pub nodes: Option<CompactNodes<'a>>,
+    pub nodes6: Option<CompactNodes<'a>>,
+    pub values: Option<CompactPeers<'a>>,
+    pub token: Option<&'a [u8]>,
+    /// Full bencoding of `v`.
+    pub value: Option<&'a [u8]>,
+    pub key: Option<&'a [u8; 32]>,       // k
+    pub signature: Option<&'a [u8; 64]>, // sig
+    pub seq: Option<i64>,
+    pub interval: Option<i64>,
+    pub num: Option<i64>,
+    pub samples: Option<CompactHashes<'a>>,
+    pub seeds_filter: Option<&'a [u8; 256]>, // BFsd
+    pub peers_filter: Option<&'a [u8; 256]>, // BFpe
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorRef<'a> {
+    pub code: i64,
+    pub message: &'a str,
+}
+
+/// A message borrowing from the packet it was decoded from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageRef<'a> {
+    pub transaction_id: &'a [u8],             // t
+    pub msg_type: MessageType,                // y
+    pub query_type: Option<QueryType>,        // q
+    pub query_args: Option<QueryArgsRef<'a>>, // a
+    pub response: Option<ResponseRef<'a>>,    // r
+    pub error: Option<ErrorRef<'a>>,          // e
+    pub ip: Option<SocketAddr>,               // ip
+    pub read_only: bool,                      // ro
+    pub version: Option<&'a [u8]>,            // v
+}
+
+fn bytes<'a>(object: Object<'_, 'a>) -> Result<&'a [u8], Error> {
+    Ok(object.try_into_bytes()?)
+}
+
+fn array<'a, const N: usize>(object: Object<'_, 'a>) -> Result<&'a [u8; N], Error> {
+    let bytes = object.try_into_bytes()?;
+    bytes.try_into().map_err(|_| invalid_length!(bytes.len()))
+}
+
+fn flag(object: Object) -> Result<bool, Error> {
+    Ok(object.try_into_integer()? == "1")
+}
+
+fn nodes<'a>(object: Object<'_, 'a>, stride: usize) -> Result<CompactNodes<'a>, Error> {
+    let bytes = object.try_into_bytes()?;
+    if bytes.len() % stride != 0 {
+        return Err(invalid_length!(bytes.len()));
+    }
+    Ok(CompactNodes { bytes, stride })
+}
+
+fn hashes<'a>(object: Object<'_, 'a>) -> Result<CompactHashes<'a>, Error> {
+    let bytes = object.try_into_bytes()?;
+    if bytes.len() % 20 != 0 {
+        return Err(invalid_length!(bytes.len()));
+    }
+    Ok(CompactHashes { bytes })
+}
+
+fn want<'a>(object: Object<'_, 'a>) -> Result<WantList<'a>, Error> {
+    let mut list = object.try_into_list()?;
+    while let Some(item) = list.next_object()? {
+        Want::from_name(item.try_into_bytes()?)?;
+    }
+    let raw = list.into_raw()?;
+    Ok(WantList {
+        items: &raw[1..raw.len() - 1],
+    })
+}
+
+fn peers<'a>(object: Object<'_, 'a>) -> Result<CompactPeers<'a>, Error> {
+    let mut list = object.try_into_list()?;
+    while let Some(item) = list.next_object()? {
+        let bytes = item.try_into_bytes()?;
+        if bytes.len() != 6 && bytes.len() != 18 {
+            return Err(invalid_length!(bytes.len()));
+        }
+    }
+    Ok(CompactPeers {
+        raw: list.into_raw()?,
+    })
+}
+
+/// Full encoding of `object`, borrowed from `input`.
+fn raw_value<'a>(input: &'a [u8], object: Object<'_, 'a>) -> Result<&'a [u8], Error> {
+    let offset = |inner: &[u8]| inner.as_ptr() as usize - input.as_ptr() as usize;
+    Ok(match object {
+        Object::Bytes(b) => {
+            let start = offset(b) - 1 - (b.len().checked_ilog10().unwrap_or(0) as usize + 1);
+            &input[start..offset(b) + b.len()]
+        }
+        Object::Integer(i) => &input[offset(i.as_bytes()) - 1..offset(i.as_bytes()) + i.len() + 1],
+        Object::List(l) => l.into_raw()?,
+        Object::Dict(d) => d.into_raw()?,
+    })
+}
+
+impl<'a> QueryArgsRef<'a> {
+    fn decode(input: &'a [u8], object: Object<'_, 'a>) -> Result<Self, Error> {
+        let mut args = QueryArgsRef {
+            sender_id: &[0; 20],
+            target: None,
+            info_hash: None,
+            implied_port: None,
+            port: None,
+            token: None,
+            want: None,
+            value: None,
+            key: None,
+            signature: None,
+            seq: None,
+            cas: None,
+            salt: None,
+            scrape: None,
+            noseed: None,
+        };
+        let mut sender_id = None;
+
+        let mut dict = object.try_into_dictionary()?;
+        while let Some(pair) = dict.next_pair()? {
+            match pair {
+                (b"cas", v) => args.cas = i64::decode_bencode_object(v).context("cas").map(Some)?,
+                (b"id", v) => sender_id = array(v).context("id").map(Some)?,
+                (b"implied_port", v) => {
+                    args.implied_port = flag(v).context("implied_port").map(Some)?
+                }
+                (b"info_hash", v) => args.info_hash = array(v).context("info_hash").map(Some)?,
+                (b"k", v) => args.key = array(v).context("k").map(Some)?,
+                (b"noseed", v) => args.noseed = flag(v).context("noseed").map(Some)?,
+                (b"port", v) => {
+                    args.port = v
+                        .try_into_integer()
+                        .context("port")?
+                        .parse::<u16>()
+                        .map_err(|_| invalid_value!("must be a valid port"))
+                        .context("port")
+                        .map(Some)?
+                }
+                (b"salt", v) => args.salt = bytes(v).context("salt").map(Some)?,
+                (b"scrape", v) => args.scrape = flag(v).context("scrape").map(Some)?,
+                (b"seq", v) => args.seq = i64::decode_bencode_object(v).context("seq").map(Some)?,
+                (b"sig", v) => args.signature = array(v).context("sig").map(Some)?,
+                (b"target", v) => args.target = array(v).context("target").map(Some)?,
+                (b"token", v) => args.token = bytes(v).context("token").map(Some)?,
+                (b"v", v) => args.value = raw_value(input, v).context("v").map(Some)?,
+                (b"want", v) => args.want = want(v).context("want").map(Some)?,
+                _ => continue,
+            }
+        }
+        args.sender_id = sender_id.ok_or(missing!("id"))?;
+        Ok(args)
+    }
+
+    fn to_raw(&self) -> raw::QueryArgs {
+        raw::QueryArgs {
+            sender_id: Hash::from(self.sender_id),
+            target: self.target.map(Hash::from),
+            info_hash: self.info_hash.map(Hash::from),
+            implied_port: self.implied_port,
+            port: self.port,
+            token: self.token.map(<[u8]>::to_vec),
+            want: self.want.map(|w| w.iter().collect()),
+            value: self.value.map(|v| RawValue { bytes: v.to_vec() }),
+            key: self.key.copied(),
+            signature: self.signature.copied(),
+            seq: self.seq,
+            cas: self.cas,
+            salt: self.salt.map(<[u8]>::to_vec),
+            scrape: self.scrape,
+            noseed: self.noseed,
+            extra: Extra::new(),
+        }
+    }
+}
+
+impl<'a> ResponseRef<'a> {
+    fn decode(input: &'a [u8], object: Object<'_, 'a>) -> Result<Self, Error> {
+        let mut r = ResponseRef {
+            sender_id: &[0; 20],
+            nodes: None,
+            nodes6: None,
+            values: None,
+            token: None,
+            value: None,
+            key: None,
+            signature: None,
+            seq: None,
+            interval: None,
+            num: None,
+            samples: None,
+            seeds_filter: None,
+            peers_filter: None,
+        };
+        let mut sender_id = None;
+
+        let mut dict = object.try_into_dictionary()?;
+        while let Some(pair) = dict.next_pair()? {
+            match pair {
+                (b"BFpe", v) => r.peers_filter = array(v).context("BFpe").map(Some)?,
+                (b"BFsd", v) => r.seeds_filter = array(v).context("BFsd").map(Some)?,
+                (b"id", v) => sender_id = array(v).context("id").map(Some)?,
+                (b"interval", v) => {
+                    r.interval = i64::decode_bencode_object(v)
+                        .context("interval")
+                        .map(Some)?
+                }
+                (b"k", v) => r.key = array(v).context("k").map(Some)?,
+                (b"nodes", v) => r.nodes = nodes(v, 26).context("nodes").map(Some)?,
+                (b"nodes6", v) => r.nodes6 = nodes(v, 38).context("nodes6").map(Some)?,
+                (b"num", v) => r.num = i64::decode_bencode_object(v).context("num").map(Some)?,
+                (b"samples", v) => r.samples = hashes(v).context("samples").map(Some)?,
+                (b"seq", v) => r.seq = i64::decode_bencode_object(v).context("seq").map(Some)?,
+                (b"sig", v) => r.signature = array(v).context("sig").map(Some)?,
+                (b"token", v) => r.token = bytes(v).context("token").map(Some)?,
+                (b"v", v) => r.value = raw_value(input, v).context("v").map(Some)?,
+                (b"values", v) => r.values = peers(v).context("values").map(Some)?,
+                _ => continue,
+            }
+        }
+        r.sender_id = sender_id.ok_or(missing!("id"))?;
+        Ok(r)
+    }
+
+    fn to_raw(&self) -> raw::Response {
+        raw::Response {
+            sender_id: Hash::from(self.sender_id),
+            nodes: self.nodes.map(|n| n.iter().collect()),
+            nodes6: self.nodes6.map(|n| n.iter().collect()),
+            values: self.values.map(|v| v.iter().collect()),
+            token: self.token.map(<[u8]>::to_vec),
+            value: self.value.map(|v| RawValue { bytes: v.to_vec() }),
+            key: self.key.copied(),
+            signature: self.signature.copied(),
+            seq: self.seq,
+            interval: self.interval,
+            num: self.num,
+            samples: self.samples.map(|s| s.iter().map(Hash::from).collect()),
+            seeds_filter: self.seeds_filter.map(|b| ScrapeBloomFilter {
+                bytes: Box::new(*b),
+            }),
+            peers_filter: self.peers_filter.map(|b| ScrapeBloomFilter {
+                bytes: Box::new(*b),
+            }),
+            extra: Extra::new(),
+        }
+    }
+}
+
+impl<'a> ErrorRef<'a> {
+    fn decode(object: Object<'_, 'a>) -> Result<Self, Error> {
+        let mut list = object.try_into_list()?;
+        let code = list.next_object()?.ok_or(missing!("code"))?;
+        let code = i64::decode_bencode_object(code).context("code")?;
+        let message = list.next_object()?.ok_or(missing!("message"))?;
+        let message = std::str::from_utf8(bytes(message)?)
+            .map_err(|_| invalid_value!("must be UTF-8"))
+            .context("message")?;
+        Ok(ErrorRef { code, message })
+    }
+}
+
+impl<'a> MessageRef<'a> {
+    pub fn decode(input: &'a [u8]) -> Result<Self, Error> {
+        let mut decoder =
+            Decoder::new(input).with_max_depth(raw::Message::EXPECTED_RECURSION_DEPTH);
+        let object = decoder.next_object()?.ok_or(missing!("message"))?;
+
+        let mut transaction_id = None;
+        let mut msg_type = None;
+        let mut query_type = None;
+        let mut query_args = None;
+        let mut response = None;
+        let mut error = None;
+        let mut ip = None;
+        let mut read_only = false;
+        let mut version = None;
+
+        let mut dict = object.try_into_dictionary()?;
+        while let Some(pair) = dict.next_pair()? {
+            match pair {
+                (b"a", v) => query_args = QueryArgsRef::decode(input, v).context("a").map(Some)?,
+                (b"e", v) => error = ErrorRef::decode(v).context("e").map(Some)?,
+                (b"ip", v) => {
+                    ip = SocketAddrWrap::decode_bencode_object(v)
+                        .context("ip")
+                        .map(|i| Some(i.into()))?
+                }
+                (b"q", v) => {
+                    query_type = QueryType::decode_bencode_object(v).context("q").map(Some)?
+                }
+                (b"r", v) => response = ResponseRef::decode(input, v).context("r").map(Some)?,
+                (b"ro", v) => read_only = flag(v).context("ro")?,
+                (b"t", v) => transaction_id = bytes(v).context("t").map(Some)?,
+                (b"v", v) => version = bytes(v).context("v").map(Some)?,
+                (b"y", v) => {
+                    msg_type = MessageType::decode_bencode_object(v)
+                        .context("y")
+                        .map(Some)?
+                }
+                _ => continue,
+            }
+        }
+        Ok(MessageRef {
+            transaction_id: transaction_id.ok_or(missing!("t"))?,
+            msg_type: msg_type.ok_or(missing!("y"))?,
+            query_type,
+            query_args,
+            response,
+            error,
+            ip,
+            read_only,
+            version,
+        })
+    }
+
+    /// Sender of a query or response.
+    pub fn sender_id(&self) -> Option<&'a [u8; 20]> {
+        match (&self.query_args, &self.response) {
+            (Some(a), _) => Some(a.sender_id),
+            (_, Some(r)) => Some(r.sender_id),
+            _ => None,
+        }
+    }
+
+    /// Copy into the owned types, checking the fields each message type requires.
+    pub fn to_owned(&self) -> Result<Message, Error> {
+        Message::from_raw(raw::Message {
+            transaction_id: TransactionId::from(self.transaction_id),
+            msg_type: self.msg_type.clone(),
+            query_type: self.query_type,
+            query_args: self.query_args.as_ref().map(QueryArgsRef::to_raw),
+            response: self.response.as_ref().map(ResponseRef::to_raw),
+            error: self.error.as_ref().map(|e| raw::Error {
+                code: e.code,
+                message: e.message.to_string(),
+                extra: Vec::new(),
+            }),
+            ip: self.ip,
+            read_only: self.read_only,
+            version: self.version.map(ClientVersion::from),
+            extra: Extra::new(),
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    borrowed::MessageRef,
+    raw::{MessageType, QueryType, Want},
+    Error, Message,
+};
+
+pub(crate) const PACKETS: &[&[u8]] = &[
+    b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+    b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz1234564:wantl2:n42:n6ee1:q9:find_node1:t2:aa1:y1:qe",
+    b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234566:noseedi0e6:scrapei1ee1:q9:get_peers1:t2:aa1:y1:qe",
+    b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
+    b"d1:ad2:id20:abcdefghij01234567893:seqi4e6:target20:mnopqrstuvwxyz123456e1:q3:get1:t2:aa1:y1:qe",
+    b"d1:ad2:id20:abcdefghij01234567895:token8:aoeusnth1:v12:Hello World!e1:q3:put1:t2:aa1:y1:qe",
+    b"d1:ad3:casi3e2:id20:abcdefghij01234567891:k32:kkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkk4:salt6:foobar3:seqi4e3:sig64:ssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssss5:token8:aoeusnth1:vl1:ad1:bi1eeee1:q3:put1:t2:aa1:y1:qe",
+    b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q17:sample_infohashes1:t2:aa1:y1:qe",
+    b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping2:roi1e1:t2:aa1:y1:qe",
+    b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:ABCDaa18:ABCDEFGHIJKLMNOPaaee1:t2:aa1:y1:re",
+    b"d1:rd2:id20:abcdefghij01234567895:nodes26:mnopqrstuvwxyz123456ABCDaa6:nodes638:11111111111111111111ABCDEFGHIJKLMNOPaae1:t2:aa1:y1:re",
+    b"d1:rd2:id20:abcdefghij01234567891:k32:kkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkk3:seqi4e3:sig64:ssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssss5:token8:aoeusnth1:vi-12ee1:t2:aa1:y1:re",
+    b"d1:rd2:id20:abcdefghij01234567898:intervali21600e5:nodes26:mnopqrstuvwxyz123456ABCDaa3:numi154e7:samples40:1111111111111111111122222222222222222222e1:t2:aa1:y1:re",
+    b"d2:ip6:ABCDaa1:rd2:id20:abcdefghij0123456789e1:t2:aa1:y1:re",
+    b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:v4:LT\x01\x021:y1:ee",
+];
+
+#[test]
+fn test_to_owned() {
+    for bytes in PACKETS {
+        let m = MessageRef::decode(bytes).unwrap();
+        assert_eq!(m.to_owned().unwrap(), Message::decode(bytes).unwrap());
+        assert_eq!(m.to_owned().unwrap().encode().unwrap(), *bytes);
+    }
+}
+
+#[test]
+fn test_borrowed() {
+    let bytes = PACKETS[9];
+    let m = MessageRef::decode(bytes).unwrap();
+    assert_eq!(m.msg_type, MessageType::Response);
+    assert_eq!(m.transaction_id, b"aa");
+    assert_eq!(m.sender_id(), Some(b"abcdefghij0123456789"));
+    let r = m.response.unwrap();
+    let token = r.token.unwrap();
+    assert_eq!(token, b"aoeusnth");
+    assert!(bytes.as_ptr_range().contains(&token.as_ptr()));
+    let peers: Vec<_> = r.values.unwrap().iter().collect();
+    assert_eq!(
+        peers,
+        vec![
+            "65.66.67.68:24929".parse().unwrap(),
+            "[4142:4344:4546:4748:494a:4b4c:4d4e:4f50]:24929"
+                .parse()
+                .unwrap(),
+        ]
+    );
+
+    let r = MessageRef::decode(PACKETS[10]).unwrap().response.unwrap();
+    let nodes = r.nodes.unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(
+        nodes.iter().next().unwrap().addr,
+        "65.66.67.68:24929".parse().unwrap()
+    );
+    assert_eq!(r.nodes6.unwrap().len(), 1);
+
+    let r = MessageRef::decode(PACKETS[12]).unwrap().response.unwrap();
+    let samples: Vec<_> = r.samples.unwrap().iter().collect();
+    assert_eq!(
+        samples,
+        vec![b"11111111111111111111", b"22222222222222222222"]
+    );
+
+    let a = MessageRef::decode(PACKETS[1]).unwrap().query_args.unwrap();
+    let want = a.want.unwrap();
+    assert_eq!(want.len(), 2);
+    assert_eq!(want.iter().collect::<Vec<_>>(), vec![Want::N4, Want::N6]);
+
+    let m = MessageRef::decode(PACKETS[6]).unwrap();
+    assert_eq!(m.query_type, Some(QueryType::Put));
+    let a = m.query_args.unwrap();
+    assert_eq!(a.value, Some(&b"l1:ad1:bi1eee"[..]));
+    assert_eq!(a.salt, Some(&b"foobar"[..]));
+
+    let m = MessageRef::decode(PACKETS[5]).unwrap();
+    assert_eq!(m.query_args.unwrap().value, Some(&b"12:Hello World!"[..]));
+    let m = MessageRef::decode(PACKETS[11]).unwrap();
+    assert_eq!(m.response.unwrap().value, Some(&b"i-12e"[..]));
+
+    let m = MessageRef::decode(PACKETS[14]).unwrap();
+    assert_eq!(m.error.unwrap().message, "A Generic Error Ocurred");
+    assert_eq!(m.version, Some(&b"LT\x01\x02"[..]));
+}
+
+#[test]
+fn test_errors() {
+    let e =
+        MessageRef::decode(b"d1:ad2:id19:abcdefghij012345678e1:q4:ping1:t2:aa1:y1:qe").unwrap_err();
+    assert!(matches!(e, Error::InvalidLength { path, len: 19 } if path == "a.id"));
+
+    let e = MessageRef::decode(b"d1:rd2:id20:abcdefghij01234567896:valuesl5:ABCDaee1:t2:aa1:y1:re")
+        .unwrap_err();
+    assert!(matches!(e, Error::InvalidLength { path, len: 5 } if path == "r.values"));
+
+    let m = MessageRef::decode(b"d1:ad2:id20:abcdefghij0123456789e1:q9:find_node1:t2:aa1:y1:qe")
+        .unwrap();
+    let e = m.to_owned().unwrap_err();
+    assert!(matches!(e, Error::MissingField { path } if path == "a.target"));
+
+    // compact lists with a partial entry are rejected, not truncated
+    let e = MessageRef::decode(
+        b"d1:rd2:id20:abcdefghij01234567895:nodes27:mnopqrstuvwxyz123456ABCDaaxe1:t2:aa1:y1:re",
+    )
+    .unwrap_err();
+    assert!(matches!(e, Error::InvalidLength { path, len: 27 } if path == "r.nodes"));
+    let e = MessageRef::decode(
+        b"d1:rd2:id20:abcdefghij01234567897:samples21:111111111111111111112e1:t2:aa1:y1:re",
+    )
+    .unwrap_err();
+    assert!(matches!(e, Error::InvalidLength { path, len: 21 } if path == "r.samples"));
+    let e = MessageRef::decode(
+        b"d1:ad2:id20:abcdefghij01234567894:wantl2:n42:n5ee1:q4:ping1:t2:aa1:y1:qe",
+    )
+    .unwrap_err();
+    assert!(matches!(e, Error::InvalidValue { path, .. } if path == "a.want"));
+}
@@ -0,0 +1,25 @@
+//! Time source for the routing table and transaction manager.
+
+use std::time::{Duration, Instant};
+
+/// Stands in for deadlines too far out for `Instant`.
+const CENTURY: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Source of the current time, so tests can move it forward.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// `now + timeout`, capped at a century for huge timeouts.
+pub(crate) fn deadline(now: Instant, timeout: Duration) -> Instant {
+    now.checked_add(timeout).unwrap_or(now + CENTURY)
+}
@@ -0,0 +1,42 @@
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::clock::{self, Clock};
+
+#[derive(Clone)]
+pub(crate) struct MockClock(Rc<Cell<Instant>>);
+
+impl MockClock {
+    pub(crate) fn new() -> Self {
+        MockClock(Rc::new(Cell::new(Instant::now())))
+    }
+
+    pub(crate) fn advance(&self, d: Duration) {
+        self.0.set(self.0.get() + d);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.0.get()
+    }
+}
+
+#[test]
+fn test_mock_clock() {
+    let clock = MockClock::new();
+    let start = clock.now();
+    clock.clone().advance(Duration::from_secs(1));
+    assert_eq!(clock.now() - start, Duration::from_secs(1));
+}
+
+#[test]
+fn test_deadline() {
+    let now = Instant::now();
+    let second = Duration::from_secs(1);
+    assert_eq!(clock::deadline(now, second), now + second);
+    assert!(clock::deadline(now, Duration::MAX) > now + second);
+}
@@ -0,0 +1,83 @@
+//! XOR metric over node IDs and info hashes.
+
+use std::fmt;
+
+use rand::Rng;
+
+use crate::raw::{Hash, Node};
+
+/// XOR of two IDs, ordered as a 160-bit big-endian integer.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct Distance {
+    pub bytes: [u8; 20],
+}
+
+impl Distance {
+    pub const ZERO: Distance = Distance { bytes: [0; 20] };
+
+    /// Number of leading zero bits, 160 for a zero distance.
+    pub fn leading_zeros(&self) -> u32 {
+        match self.bytes.iter().position(|&b| b != 0) {
+            Some(i) => i as u32 * 8 + self.bytes[i].leading_zeros(),
+            None => 160,
+        }
+    }
+}
+
+impl fmt::Debug for Distance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.bytes {
+            write!(f, "{:02x}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl Hash {
+    pub fn distance(&self, other: &Hash) -> Distance {
+        let mut bytes = [0; 20];
+        for (d, (a, b)) in bytes.iter_mut().zip(self.bytes.iter().zip(&other.bytes)) {
+            *d = a ^ b;
+        }
+        Distance { bytes }
+    }
+
+    /// Number of leading bits shared with `other`, 160 if they are equal.
+    pub fn common_prefix_len(&self, other: &Hash) -> usize {
+        self.distance(other).leading_zeros() as usize
+    }
+
+    /// Bit `i`, counting from the most significant bit of the first byte.
+    ///
+    /// Panics if `i >= 160`.
+    pub fn bit(&self, i: usize) -> bool {
+        self.bytes[i / 8] & (0x80 >> (i % 8)) != 0
+    }
+
+    /// A random ID sharing exactly `prefix_len` leading bits with `self`,
+    /// i.e. one that falls into routing table bucket `prefix_len`. Used to
+    /// refresh a bucket.
+    ///
+    /// Panics if `prefix_len >= 160`.
+    pub fn random_in_bucket<R: Rng + ?Sized>(&self, prefix_len: usize, rng: &mut R) -> Hash {
+        assert!(
+            prefix_len < 160,
+            "prefix length {} out of range",
+            prefix_len
+        );
+        let mut bytes: [u8; 20] = rng.gen();
+        let (byte, bit) = (prefix_len / 8, prefix_len % 8);
+        bytes[..byte].copy_from_slice(&self.bytes[..byte]);
+        // keep the first `bit` bits, flip the next one, the rest stays random
+        let keep = !(0xffu8 >> bit);
+        let flip = 0x80u8 >> bit;
+        bytes[byte] =
+            (self.bytes[byte] & keep) | (!self.bytes[byte] & flip) | (bytes[byte] & !keep & !flip);
+        Hash { bytes }
+    }
+}
+
+/// Sort `nodes` closest to `target` first.
+pub fn sort_by_distance(nodes: &mut [Node], target: &Hash) {
+    nodes.sort_by_cached_key(|node| target.distance(&node.id));
+}
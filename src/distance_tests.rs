@@ -0,0 +1,100 @@
+use proptest::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    distance::{sort_by_distance, Distance},
+    raw::{Hash, Node},
+};
+
+fn hash() -> impl Strategy<Value = Hash> {
+    any::<[u8; 20]>().prop_map(|bytes| Hash { bytes })
+}
+
+/// `a + b` as a 168-bit big-endian integer.
+fn add(a: &Distance, b: &Distance) -> [u8; 21] {
+    let mut sum = [0; 21];
+    let mut carry = 0;
+    for i in (0..20).rev() {
+        let s = a.bytes[i] as u16 + b.bytes[i] as u16 + carry;
+        sum[i + 1] = s as u8;
+        carry = s >> 8;
+    }
+    sum[0] = carry as u8;
+    sum
+}
+
+fn widen(d: &Distance) -> [u8; 21] {
+    let mut w = [0; 21];
+    w[1..].copy_from_slice(&d.bytes);
+    w
+}
+
+proptest! {
+    #[test]
+    fn symmetry(a in hash(), b in hash()) {
+        prop_assert_eq!(a.distance(&b), b.distance(&a));
+    }
+
+    #[test]
+    fn identity(a in hash(), b in hash()) {
+        prop_assert_eq!(a.distance(&a), Distance::ZERO);
+        prop_assert_eq!(a.distance(&b) == Distance::ZERO, a == b);
+    }
+
+    #[test]
+    fn triangle_inequality(a in hash(), b in hash(), c in hash()) {
+        let ac = widen(&a.distance(&c));
+        prop_assert!(ac <= add(&a.distance(&b), &b.distance(&c)));
+    }
+
+    #[test]
+    fn common_prefix(a in hash(), b in hash()) {
+        let len = a.common_prefix_len(&b);
+        for i in 0..len {
+            prop_assert_eq!(a.bit(i), b.bit(i));
+        }
+        if len < 160 {
+            prop_assert_ne!(a.bit(len), b.bit(len));
+        }
+    }
+
+    #[test]
+    fn random_in_bucket(a in hash(), prefix_len in 0..160usize, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let b = a.random_in_bucket(prefix_len, &mut rng);
+        prop_assert_eq!(a.common_prefix_len(&b), prefix_len);
+    }
+
+    #[test]
+    fn sorted(target in hash(), ids in prop::collection::vec(hash(), 0..32)) {
+        let mut nodes: Vec<Node> = ids
+            .into_iter()
+            .map(|id| Node { id, addr: ([127, 0, 0, 1], 6881).into() })
+            .collect();
+        sort_by_distance(&mut nodes, &target);
+        for pair in nodes.windows(2) {
+            prop_assert!(target.distance(&pair[0].id) <= target.distance(&pair[1].id));
+        }
+    }
+}
+
+#[test]
+fn test_bits() {
+    let mut bytes = [0; 20];
+    bytes[0] = 0b0100_0000;
+    bytes[19] = 1;
+    let a = Hash { bytes };
+    assert!(!a.bit(0));
+    assert!(a.bit(1));
+    assert!(a.bit(159));
+    assert_eq!((0..160).filter(|&i| a.bit(i)).count(), 2);
+
+    let zero = Hash { bytes: [0; 20] };
+    assert_eq!(a.distance(&zero).leading_zeros(), 1);
+    assert_eq!(a.common_prefix_len(&zero), 1);
+    assert_eq!(zero.common_prefix_len(&zero), 160);
+    assert_eq!(
+        format!("{:?}", a.distance(&zero)),
+        "4000000000000000000000000000000000000001"
+    );
+}
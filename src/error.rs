@@ -0,0 +1,333 @@
+use std::fmt::{self, Display};
+
+use bendy::decoding::{Decoder, ErrorKind, FromBencode, Object};
+
+use crate::{
+    raw::{self, Hash, MessageType, TransactionId},
+    transaction::NoIdsLeft,
+    ErrorMessage,
+};
+
+/// Errors returned while decoding or encoding a message.
+///
+/// `path` is the dotted key path of the offending value, e.g. `a.id`.
+#[derive(Debug, Clone)]
+pub enum Error {
+    MissingField {
+        path: String,
+    },
+    /// A string has the wrong length, e.g. a 19 byte node ID.
+    InvalidLength {
+        path: String,
+        len: usize,
+    },
+    InvalidValue {
+        path: String,
+        reason: &'static str,
+    },
+    /// `q` is not a query type this crate knows.
+    UnknownQuery {
+        name: Vec<u8>,
+    },
+    /// `y` is not one of `q`, `r` or `e`.
+    UnknownMessageType {
+        name: Vec<u8>,
+    },
+    /// Broken bencode, or a value of the wrong bencode type.
+    Bencode {
+        path: String,
+        source: bendy::decoding::Error,
+    },
+    Encode(bendy::encoding::Error),
+    /// The buffer given to `encode_into` can't hold the message.
+    BufferTooSmall {
+        needed: usize,
+        remaining: usize,
+    },
+}
+
+impl Error {
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::MissingField { path }
+            | Self::InvalidLength { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::Bencode { path, .. } => Some(path),
+            Self::UnknownQuery { .. } => Some("q"),
+            Self::UnknownMessageType { .. } => Some("y"),
+            Self::Encode(_) | Self::BufferTooSmall { .. } => None,
+        }
+    }
+
+    /// Prepend `key` to the path.
+    pub(crate) fn context(mut self, key: impl Display) -> Self {
+        match &mut self {
+            Self::MissingField { path }
+            | Self::InvalidLength { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::Bencode { path, .. } => {
+                *path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", key, path)
+                };
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField { path } => write!(f, "missing field `{}`", path),
+            Self::InvalidLength { path, len } => write!(f, "invalid length {} for `{}`", len, path),
+            Self::InvalidValue { path, reason } => {
+                write!(f, "invalid value for `{}`: {}", path, reason)
+            }
+            Self::UnknownQuery { name } => {
+                write!(f, "unknown query `{}`", String::from_utf8_lossy(name))
+            }
+            Self::UnknownMessageType { name } => {
+                write!(
+                    f,
+                    "unknown message type `{}`",
+                    String::from_utf8_lossy(name)
+                )
+            }
+            Self::Bencode { path, source } if path.is_empty() => write!(f, "{}", source),
+            Self::Bencode { path, source } => write!(f, "`{}`: {}", path, source),
+            Self::Encode(source) => write!(f, "{}", source),
+            Self::BufferTooSmall { needed, remaining } => write!(
+                f,
+                "buffer too small, {} bytes needed and {} remaining",
+                needed, remaining
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Bencode { source, .. } => Some(source),
+            Self::Encode(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Our own errors travel through `FromBencode` impls as malformed content,
+/// unwrap them again here.
+impl From<bendy::decoding::Error> for Error {
+    fn from(e: bendy::decoding::Error) -> Self {
+        let kind = std::error::Error::source(&e).and_then(|s| s.downcast_ref::<ErrorKind>());
+        match kind {
+            Some(ErrorKind::MalformedContent { source }) => {
+                if let Some(inner) = source.downcast_ref::<Error>() {
+                    return inner.clone();
+                }
+            }
+            Some(ErrorKind::MissingField { field }) => {
+                return Error::MissingField {
+                    path: field.clone(),
+                }
+            }
+            _ => {}
+        }
+        Error::Bencode {
+            path: String::new(),
+            source: e,
+        }
+    }
+}
+
+impl From<Error> for bendy::decoding::Error {
+    fn from(e: Error) -> Self {
+        bendy::decoding::Error::malformed_content(e)
+    }
+}
+
+impl From<bendy::encoding::Error> for Error {
+    fn from(e: bendy::encoding::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+/// A message that failed to decode, with whatever could still be read from it.
+#[derive(Debug, Clone)]
+pub struct DecodeFailure {
+    pub transaction_id: Option<TransactionId>,
+    pub msg_type: Option<MessageType>,
+    /// Raw `q`, kept even when the query is unknown.
+    pub query: Option<Vec<u8>>,
+    pub sender_id: Option<Hash>,
+    pub error: Error,
+}
+
+impl DecodeFailure {
+    pub(crate) fn recover(bytes: &[u8], error: Error) -> Self {
+        let mut failure = DecodeFailure {
+            transaction_id: None,
+            msg_type: None,
+            query: None,
+            sender_id: None,
+            error,
+        };
+        let mut decoder =
+            Decoder::new(bytes).with_max_depth(raw::Message::EXPECTED_RECURSION_DEPTH);
+        let mut dict = match decoder.next_object() {
+            Ok(Some(object)) => match object.try_into_dictionary() {
+                Ok(dict) => dict,
+                Err(_) => return failure,
+            },
+            _ => return failure,
+        };
+        while let Ok(Some(pair)) = dict.next_pair() {
+            match pair {
+                (b"t", v) => failure.transaction_id = TransactionId::decode_bencode_object(v).ok(),
+                (b"y", v) => failure.msg_type = MessageType::decode_bencode_object(v).ok(),
+                (b"q", v) => failure.query = v.try_into_bytes().ok().map(<[u8]>::to_vec),
+                (b"a" | b"r", v) => failure.sender_id = recover_id(v),
+                _ => {}
+            }
+        }
+        failure
+    }
+
+    /// The error reply BEP 5 asks for, echoing `t`. `None` if `t` is unknown,
+    /// or if the failed message was itself a response or an error.
+    pub fn reply(&self) -> Option<ErrorMessage> {
+        if matches!(
+            self.msg_type,
+            Some(MessageType::Response | MessageType::Error)
+        ) {
+            return None;
+        }
+        let transaction_id = self.transaction_id.clone()?;
+        Some(match self.error {
+            Error::UnknownQuery { .. } => ErrorMessage::method_unknown(transaction_id),
+            _ => ErrorMessage::protocol(transaction_id, self.error.to_string()),
+        })
+    }
+}
+
+fn recover_id(object: Object) -> Option<Hash> {
+    let mut dict = object.try_into_dictionary().ok()?;
+    let mut id = None;
+    // read to the end so the outer dict can go on
+    while let Ok(Some(pair)) = dict.next_pair() {
+        if let (b"id", v) = pair {
+            id = Hash::decode_bencode_object(v).ok();
+        }
+    }
+    id
+}
+
+impl Display for DecodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for DecodeFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Errors of a query sent over a socket.
+#[derive(Debug)]
+pub enum QueryError {
+    /// No response after all retries.
+    Timeout,
+    /// The remote node answered with an error message.
+    Remote(ErrorMessage),
+    /// The message to send is a response or an error.
+    NotAQuery,
+    /// Every transaction ID is taken by queries to the address.
+    NoIdsLeft(NoIdsLeft),
+    Encode(Error),
+    Io(std::io::Error),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "query timed out"),
+            Self::Remote(e) => write!(f, "error {}: {}", i64::from(e.code), e.message),
+            Self::NotAQuery => write!(f, "not a query"),
+            Self::NoIdsLeft(e) => e.fmt(f),
+            Self::Encode(e) => e.fmt(f),
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Encode(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::NoIdsLeft(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for QueryError {
+    fn from(e: Error) -> Self {
+        QueryError::Encode(e)
+    }
+}
+
+impl From<NoIdsLeft> for QueryError {
+    fn from(e: NoIdsLeft) -> Self {
+        QueryError::NoIdsLeft(e)
+    }
+}
+
+impl From<std::io::Error> for QueryError {
+    fn from(e: std::io::Error) -> Self {
+        QueryError::Io(e)
+    }
+}
+
+pub(crate) trait ResultExt<T> {
+    fn context(self, key: impl Display) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn context(self, key: impl Display) -> Result<T, Error> {
+        self.map_err(|e| e.into().context(key))
+    }
+}
+
+macro_rules! missing {
+    ($m:expr) => {
+        $crate::error::Error::MissingField {
+            path: $m.to_string(),
+        }
+    };
+}
+macro_rules! invalid_length {
+    ($len:expr) => {
+        $crate::error::Error::InvalidLength {
+            path: String::new(),
+            len: $len,
+        }
+    };
+}
+macro_rules! invalid_value {
+    ($reason:expr) => {
+        $crate::error::Error::InvalidValue {
+            path: String::new(),
+            reason: $reason,
+        }
+    };
+}
+
+pub(crate) use invalid_length;
+pub(crate) use invalid_value;
+pub(crate) use missing;
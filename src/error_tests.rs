@@ -0,0 +1,103 @@
+use crate::{raw::MessageType, Error, ErrorCode, ErrorMessage, Message};
+
+fn decode_err(bytes: &[u8]) -> Error {
+    Message::decode(bytes).unwrap_err()
+}
+
+#[test]
+fn test_missing_field() {
+    let e = decode_err(b"d1:ad1:xi1ee1:q4:ping1:t2:aa1:y1:qe");
+    assert!(matches!(&e, Error::MissingField { path } if path == "a.id"));
+    assert_eq!(e.to_string(), "missing field `a.id`");
+
+    let e = decode_err(b"d1:ad2:id20:abcdefghij0123456789e1:q9:find_node1:t2:aa1:y1:qe");
+    assert!(matches!(&e, Error::MissingField { path } if path == "a.target"));
+
+    let e = decode_err(b"d1:q4:ping1:y1:qe");
+    assert_eq!(e.path(), Some("t"));
+}
+
+#[test]
+fn test_invalid_length() {
+    let e = decode_err(b"d1:ad2:id19:abcdefghij012345678e1:q4:ping1:t2:aa1:y1:qe");
+    assert!(matches!(&e, Error::InvalidLength { path, len: 19 } if path == "a.id"));
+
+    let e = decode_err(b"d1:rd2:id20:mnopqrstuvwxyz1234565:nodes3:abce1:t2:aa1:y1:re");
+    assert!(matches!(&e, Error::InvalidLength { path, len: 3 } if path == "r.nodes"));
+}
+
+#[test]
+fn test_unknown() {
+    let e = decode_err(b"d1:ad2:id20:abcdefghij0123456789e1:q3:foo1:t2:aa1:y1:qe");
+    assert!(matches!(&e, Error::UnknownQuery { name } if name == b"foo"));
+    assert_eq!(e.path(), Some("q"));
+
+    let e = decode_err(b"d1:t2:aa1:y1:xe");
+    assert!(matches!(&e, Error::UnknownMessageType { name } if name == b"x"));
+}
+
+#[test]
+fn test_bencode() {
+    let e = decode_err(b"d1:t2:aa1:yi1ee");
+    assert!(matches!(&e, Error::Bencode { path, .. } if path == "y"));
+
+    let e = decode_err(b"d1:t2:aa");
+    assert!(matches!(&e, Error::Bencode { path, .. } if path.is_empty()));
+}
+
+#[test]
+fn test_decode_lenient() {
+    let bytes = b"d1:ad2:id20:abcdefghij01234567896:target19:mnopqrstuvwxyz12345e1:q9:find_node1:t2:aa1:y1:qe";
+    let failure = Message::decode_lenient(bytes).unwrap_err();
+    assert_eq!(failure.transaction_id, Some(b"aa".into()));
+    assert_eq!(failure.msg_type, Some(MessageType::Query));
+    assert_eq!(failure.query.as_deref(), Some(&b"find_node"[..]));
+    assert_eq!(failure.sender_id, Some(b"abcdefghij0123456789".into()));
+    assert_eq!(failure.error.path(), Some("a.target"));
+
+    let reply = failure.reply().unwrap();
+    assert_eq!(reply.code, ErrorCode::Protocol);
+    assert_eq!(
+        reply.encode().unwrap(),
+        b"d1:eli203e32:invalid length 19 for `a.target`e1:t2:aa1:y1:ee"
+    );
+
+    let failure =
+        Message::decode_lenient(b"d1:ad2:id20:abcdefghij0123456789e1:q3:foo1:t2:aa1:y1:qe")
+            .unwrap_err();
+    assert_eq!(
+        failure.reply().unwrap(),
+        ErrorMessage::method_unknown(b"aa")
+    );
+
+    let failure = Message::decode_lenient(b"d1:rd2:id3:abce1:t2:aa1:y1:re").unwrap_err();
+    assert_eq!(failure.sender_id, None);
+    assert!(failure.reply().is_none());
+
+    let failure = Message::decode_lenient(b"d1:q4:ping1:y1:qe").unwrap_err();
+    assert_eq!(failure.transaction_id, None);
+    assert!(failure.reply().is_none());
+}
+
+#[test]
+fn test_error_code() {
+    for code in [201, 202, 203, 204, 205, 206, 207, 301, 302, 999] {
+        assert_eq!(i64::from(ErrorCode::from(code)), code);
+    }
+    assert_eq!(ErrorCode::from(204), ErrorCode::MethodUnknown);
+    assert_eq!(ErrorCode::from(999), ErrorCode::Other(999));
+    // a hand-built Other for a known code is the same code
+    assert_eq!(ErrorCode::Other(201), ErrorCode::Generic);
+    assert_ne!(ErrorCode::Other(202), ErrorCode::Generic);
+    let e = ErrorMessage::new(24929u16, ErrorCode::Other(203), "bad");
+    assert_eq!(e, ErrorMessage::protocol(24929u16, "bad"));
+
+    let Message::Error(e) = Message::decode(b"d1:eli302e7:too olde1:t2:aa1:y1:ee").unwrap() else {
+        panic!("expected an error message");
+    };
+    assert_eq!(e.code, ErrorCode::SequenceLessThanCurrent);
+    assert_eq!(
+        ErrorMessage::method_unknown(24929u16).encode().unwrap(),
+        b"d1:eli204e14:Method Unknowne1:t2:aa1:y1:ee"
+    );
+}
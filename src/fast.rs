@@ -0,0 +1,471 @@
+//! Single-pass KRPC decoder that skips bendy's generic object machinery.
+//!
+//! Produces the same [`raw::Message`] as [`FromBencode`](bendy::decoding::FromBencode)
+//! and applies the same checks: canonical integers, sorted keys and the same
+//! nesting limit.
+
+use std::net::SocketAddr;
+
+use bendy::decoding::FromBencode;
+
+use crate::{
+    error::{invalid_length, invalid_value, missing, ResultExt},
+    raw::{
+        self, ClientVersion, Extra, Hash, MessageType, Node, QueryArgs, QueryType, RawValue,
+        ScrapeBloomFilter, SocketAddrWrap, TransactionId, Want, FLAG_VALUES, RO_VALUES,
+    },
+    Error,
+};
+
+const MAX_DEPTH: usize = raw::Message::EXPECTED_RECURSION_DEPTH;
+
+/// Decode a message, dropping unrecognised keys.
+pub fn decode(bytes: &[u8]) -> Result<raw::Message, Error> {
+    Parser::new(bytes, false).message()
+}
+
+/// Like [`raw::Message::from_bencode_lossless`], keys that are unknown or don't
+/// fit their field go to `extra`.
+pub fn decode_lossless(bytes: &[u8]) -> Result<raw::Message, Error> {
+    Parser::new(bytes, true).message()
+}
+
+fn bencode_error(expected: &str, discovered: &str) -> Error {
+    Error::Bencode {
+        path: String::new(),
+        source: bendy::decoding::Error::unexpected_token(expected, discovered),
+    }
+}
+
+fn malformed<E: std::error::Error + Send + Sync + 'static>(e: E) -> Error {
+    Error::Bencode {
+        path: String::new(),
+        source: bendy::decoding::Error::malformed_content(e),
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+    lossless: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a [u8], lossless: bool) -> Self {
+        Parser {
+            input,
+            pos: 0,
+            depth: 0,
+            lossless,
+        }
+    }
+
+    fn peek(&self) -> Result<u8, Error> {
+        self.input
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| bencode_error("more input", "EOF"))
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        let discovered = match self.input.get(self.pos) {
+            None => "EOF",
+            Some(b'0'..=b'9') => "String",
+            Some(b'i') => "Num",
+            Some(b'l') => "List",
+            Some(b'd') => "Dict",
+            Some(b'e') => "End",
+            Some(_) => "invalid token",
+        };
+        bencode_error(expected, discovered)
+    }
+
+    /// Digits up to `end`, rejecting leading zeros and `-0` like bendy does.
+    fn digits(&mut self, end: u8) -> Result<&'a str, Error> {
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let len = rest
+            .iter()
+            .position(|&c| c == end)
+            .ok_or_else(|| bencode_error("a terminated number", "EOF"))?;
+        let n = &rest[..len];
+        let digits = n.strip_prefix(b"-").unwrap_or(n);
+        let valid = match digits {
+            [] => false,
+            [b'0'] => digits.len() == n.len(),
+            [b'0', ..] => false,
+            _ => digits.iter().all(u8::is_ascii_digit),
+        };
+        if !valid {
+            return Err(bencode_error(
+                "a canonical number",
+                &String::from_utf8_lossy(n),
+            ));
+        }
+        self.pos += len + 1;
+        // only ASCII digits and '-' got through
+        Ok(std::str::from_utf8(n).unwrap())
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        if !self.peek()?.is_ascii_digit() {
+            return Err(self.unexpected("String"));
+        }
+        let len: usize = self.digits(b':')?.parse().map_err(malformed)?;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.input.len())
+            .ok_or_else(|| bencode_error("more input", "EOF"))?;
+        let s = &self.input[self.pos..end];
+        self.pos = end;
+        Ok(s)
+    }
+
+    fn integer(&mut self) -> Result<&'a str, Error> {
+        if self.peek()? != b'i' {
+            return Err(self.unexpected("Num"));
+        }
+        self.pos += 1;
+        self.digits(b'e')
+    }
+
+    fn int(&mut self) -> Result<i64, Error> {
+        self.integer()?.parse().map_err(malformed)
+    }
+
+    /// See [`raw::flag`].
+    fn flag(
+        &mut self,
+        extra: &mut Extra,
+        key: &'static str,
+        values: &[&str],
+    ) -> Result<Option<bool>, Error> {
+        let i = self.integer().context(key)?;
+        Ok(raw::flag(i, key.as_bytes(), values, self.lossless, extra))
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let s = self.bytes()?;
+        s.try_into().map_err(|_| invalid_length!(s.len()))
+    }
+
+    fn hash(&mut self) -> Result<Hash, Error> {
+        Ok(Hash {
+            bytes: self.array()?,
+        })
+    }
+
+    fn enter(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid_value!("nesting too deep"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Call `f` for each pair of a dict, `f` has to consume the value.
+    fn dict(
+        &mut self,
+        mut f: impl FnMut(&mut Self, &'a [u8]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.peek()? != b'd' {
+            return Err(self.unexpected("Dict"));
+        }
+        self.enter()?;
+        let mut last: Option<&[u8]> = None;
+        while self.peek()? != b'e' {
+            let key = self.bytes()?;
+            if last.is_some_and(|last| last >= key) {
+                return Err(bencode_error("sorted keys", &String::from_utf8_lossy(key)));
+            }
+            last = Some(key);
+            f(self, key)?;
+        }
+        self.pos += 1;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Like [`dict`](Self::dict), but in lossless mode a value `f` fails on is
+    /// read again as it is and returned, see [`raw::Message::from_bencode_lossless`].
+    fn fields(
+        &mut self,
+        mut f: impl FnMut(&mut Self, &'a [u8]) -> Result<(), Error>,
+    ) -> Result<Extra, Error> {
+        let mut rejected = Extra::new();
+        self.dict(|p, key| {
+            let (pos, depth) = (p.pos, p.depth);
+            match f(p, key) {
+                Err(_) if p.lossless => {
+                    p.pos = pos;
+                    p.depth = depth;
+                    let value = p.raw_value().context(String::from_utf8_lossy(key))?;
+                    rejected.insert(key.to_vec(), value);
+                    Ok(())
+                }
+                result => result,
+            }
+        })?;
+        Ok(rejected)
+    }
+
+    fn list(&mut self, mut f: impl FnMut(&mut Self) -> Result<(), Error>) -> Result<(), Error> {
+        if self.peek()? != b'l' {
+            return Err(self.unexpected("List"));
+        }
+        self.enter()?;
+        while self.peek()? != b'e' {
+            f(self)?;
+        }
+        self.pos += 1;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Validate any value and return its encoding.
+    fn skip(&mut self) -> Result<&'a [u8], Error> {
+        let start = self.pos;
+        match self.peek()? {
+            b'i' => {
+                self.integer()?;
+            }
+            b'l' => self.list(|p| p.skip().map(drop))?,
+            b'd' => self.dict(|p, _| p.skip().map(drop))?,
+            _ => {
+                self.bytes()?;
+            }
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    fn raw_value(&mut self) -> Result<RawValue, Error> {
+        Ok(RawValue {
+            bytes: self.skip()?.to_vec(),
+        })
+    }
+
+    fn extra(&mut self, extra: &mut Extra, key: &[u8]) -> Result<(), Error> {
+        if self.lossless {
+            let value = self.raw_value().context(String::from_utf8_lossy(key))?;
+            extra.insert(key.to_vec(), value);
+        } else {
+            self.skip().context(String::from_utf8_lossy(key))?;
+        }
+        Ok(())
+    }
+
+    fn nodes<const LEN: usize>(&mut self) -> Result<Vec<Node>, Error> {
+        let bytes = self.bytes()?;
+        if bytes.len() % LEN != 0 {
+            return Err(invalid_length!(bytes.len()));
+        }
+        bytes
+            .chunks(LEN)
+            .map(|chunk| Node::try_from(chunk).map_err(|_| invalid_value!("invalid compact node")))
+            .collect()
+    }
+
+    fn samples(&mut self) -> Result<Vec<Hash>, Error> {
+        let bytes = self.bytes()?;
+        if bytes.len() % 20 != 0 {
+            return Err(invalid_length!(bytes.len()));
+        }
+        Ok(bytes
+            .chunks(20)
+            .map(|chunk| Hash {
+                bytes: chunk.try_into().unwrap(),
+            })
+            .collect())
+    }
+
+    fn addr(&mut self) -> Result<SocketAddr, Error> {
+        let bytes = self.bytes()?;
+        SocketAddrWrap::try_from(bytes)
+            .map(SocketAddr::from)
+            .map_err(|_| invalid_length!(bytes.len()))
+    }
+
+    fn bloom_filter(&mut self) -> Result<ScrapeBloomFilter, Error> {
+        Ok(ScrapeBloomFilter {
+            bytes: Box::new(self.array()?),
+        })
+    }
+
+    fn query_args(&mut self) -> Result<QueryArgs, Error> {
+        let mut extra = Extra::new();
+        let mut sender_id = None;
+        let mut a = QueryArgs::new(Hash { bytes: [0; 20] });
+        let rejected = self.fields(|p, key| {
+            match key {
+                b"cas" => a.cas = p.int().context("cas").map(Some)?,
+                b"id" => sender_id = p.hash().context("id").map(Some)?,
+                b"implied_port" => {
+                    a.implied_port = p.flag(&mut extra, "implied_port", FLAG_VALUES)?
+                }
+                b"info_hash" => a.info_hash = p.hash().context("info_hash").map(Some)?,
+                b"port" => {
+                    a.port = p
+                        .integer()
+                        .context("port")?
+                        .parse::<u16>()
+                        .map_err(|_| invalid_value!("must be a valid port"))
+                        .context("port")
+                        .map(Some)?;
+                }
+                b"target" => a.target = p.hash().context("target").map(Some)?,
+                b"token" => a.token = p.bytes().context("token").map(|t| Some(t.to_vec()))?,
+                b"want" => {
+                    let mut want = Vec::new();
+                    p.list(|p| {
+                        want.push(Want::from_name(p.bytes()?)?);
+                        Ok(())
+                    })
+                    .context("want")?;
+                    a.want = Some(want);
+                }
+                b"k" => a.key = p.array().context("k").map(Some)?,
+                b"noseed" => a.noseed = p.flag(&mut extra, "noseed", FLAG_VALUES)?,
+                b"scrape" => a.scrape = p.flag(&mut extra, "scrape", FLAG_VALUES)?,
+                b"salt" => a.salt = p.bytes().context("salt").map(|s| Some(s.to_vec()))?,
+                b"seq" => a.seq = p.int().context("seq").map(Some)?,
+                b"sig" => a.signature = p.array().context("sig").map(Some)?,
+                b"v" => a.value = p.raw_value().context("v").map(Some)?,
+                _ => p.extra(&mut extra, key)?,
+            }
+            Ok(())
+        })?;
+        a.sender_id = sender_id.ok_or(missing!("id"))?;
+        extra.extend(rejected);
+        a.extra = extra;
+        Ok(a)
+    }
+
+    fn response(&mut self) -> Result<raw::Response, Error> {
+        let mut extra = Extra::new();
+        let mut sender_id = None;
+        let mut r = raw::Response::new(Hash { bytes: [0; 20] });
+        let rejected = self.fields(|p, key| {
+            match key {
+                b"BFpe" => r.peers_filter = p.bloom_filter().context("BFpe").map(Some)?,
+                b"BFsd" => r.seeds_filter = p.bloom_filter().context("BFsd").map(Some)?,
+                b"id" => sender_id = p.hash().context("id").map(Some)?,
+                b"nodes" => r.nodes = p.nodes::<26>().context("nodes").map(Some)?,
+                b"nodes6" => r.nodes6 = p.nodes::<38>().context("nodes6").map(Some)?,
+                b"values" => {
+                    let mut values = Vec::new();
+                    p.list(|p| {
+                        values.push(p.addr()?);
+                        Ok(())
+                    })
+                    .context("values")?;
+                    r.values = Some(values);
+                }
+                b"token" => r.token = p.bytes().context("token").map(|t| Some(t.to_vec()))?,
+                b"interval" => r.interval = p.int().context("interval").map(Some)?,
+                b"k" => r.key = p.array().context("k").map(Some)?,
+                b"num" => r.num = p.int().context("num").map(Some)?,
+                b"samples" => r.samples = p.samples().context("samples").map(Some)?,
+                b"seq" => r.seq = p.int().context("seq").map(Some)?,
+                b"sig" => r.signature = p.array().context("sig").map(Some)?,
+                b"v" => r.value = p.raw_value().context("v").map(Some)?,
+                _ => p.extra(&mut extra, key)?,
+            }
+            Ok(())
+        })?;
+        r.sender_id = sender_id.ok_or(missing!("id"))?;
+        extra.extend(rejected);
+        r.extra = extra;
+        Ok(r)
+    }
+
+    fn error(&mut self) -> Result<raw::Error, Error> {
+        let mut code = None;
+        let mut message = None;
+        let mut extra = Vec::new();
+        self.list(|p| {
+            if code.is_none() {
+                code = Some(p.int()?);
+            } else if message.is_none() {
+                let s = p.bytes()?;
+                message = Some(String::from_utf8(s.to_vec()).map_err(malformed)?);
+            } else if p.lossless {
+                extra.push(p.raw_value()?);
+            } else {
+                p.skip()?;
+            }
+            Ok(())
+        })?;
+        Ok(raw::Error {
+            code: code.ok_or(missing!("code"))?,
+            message: message.ok_or(missing!("message"))?,
+            extra,
+        })
+    }
+
+    fn message(mut self) -> Result<raw::Message, Error> {
+        let mut extra = Extra::new();
+        let mut transaction_id = None;
+        let mut msg_type = None;
+        let mut query_type = None;
+        let mut query_args = None;
+        let mut response = None;
+        let mut error = None;
+        let mut ip = None;
+        let mut read_only = false;
+        let mut version = None;
+
+        let rejected = self.fields(|p, key| {
+            match key {
+                b"t" => {
+                    transaction_id = p
+                        .bytes()
+                        .context("t")
+                        .map(|t| Some(TransactionId::from(t)))?;
+                }
+                b"y" => {
+                    msg_type = p
+                        .bytes()
+                        .and_then(MessageType::from_name)
+                        .context("y")
+                        .map(Some)?;
+                }
+                b"q" => {
+                    query_type = p
+                        .bytes()
+                        .and_then(QueryType::from_name)
+                        .context("q")
+                        .map(Some)?;
+                }
+                b"a" => query_args = p.query_args().context("a").map(Some)?,
+                b"r" => response = p.response().context("r").map(Some)?,
+                b"e" => error = p.error().context("e").map(Some)?,
+                b"ip" => ip = p.addr().context("ip").map(Some)?,
+                b"ro" => read_only = p.flag(&mut extra, "ro", RO_VALUES)?.unwrap_or(false),
+                b"v" => {
+                    version = p
+                        .bytes()
+                        .context("v")
+                        .map(|v| Some(ClientVersion::from(v)))?;
+                }
+                _ => p.extra(&mut extra, key)?,
+            }
+            Ok(())
+        })?;
+        extra.extend(rejected);
+        Ok(raw::Message {
+            transaction_id: transaction_id.ok_or(missing!("t"))?,
+            msg_type: msg_type.ok_or(missing!("y"))?,
+            query_type,
+            query_args,
+            response,
+            error,
+            ip,
+            read_only,
+            version,
+            extra,
+        })
+    }
+}
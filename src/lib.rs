@@ -1,192 +1,1085 @@
+pub mod bep33;
+#[cfg(test)]
+mod bep33_tests;
+pub mod bep42;
+#[cfg(test)]
+mod bep42_tests;
+#[cfg(feature = "bep44")]
+pub mod bep44;
+#[cfg(all(test, feature = "bep44"))]
+mod bep44_tests;
+pub mod blocking;
+#[cfg(test)]
+mod blocking_tests;
+pub mod borrowed;
+#[cfg(test)]
+mod borrowed_tests;
+pub mod clock;
+#[cfg(test)]
+mod clock_tests;
+pub mod distance;
+#[cfg(test)]
+mod distance_tests;
+pub mod error;
+#[cfg(test)]
+mod error_tests;
+#[cfg(feature = "fast-decode")]
+pub mod fast;
 pub mod raw;
 #[cfg(test)]
 mod raw_tests;
+pub mod response;
+#[cfg(test)]
+mod response_tests;
+pub mod routing;
+#[cfg(test)]
+mod routing_tests;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests;
+#[cfg(feature = "tokio")]
+pub mod socket;
+#[cfg(all(test, feature = "tokio"))]
+mod socket_tests;
+pub mod transaction;
+#[cfg(test)]
+mod transaction_tests;
+mod udp;
+#[cfg(test)]
+mod udp_tests;
+pub mod version;
+mod writer;
+#[cfg(test)]
+mod writer_tests;
 
-use std::net::SocketAddrV4;
+use std::net::SocketAddr;
 
-use bendy::{decoding::FromBencode, encoding::ToBencode};
-use raw::{missing, Hash, MessageType, Node, QueryArgs, QueryType};
+#[cfg(not(feature = "fast-decode"))]
+use bendy::decoding::FromBencode;
+use bendy::encoding::ToBencode;
+use error::missing;
+pub use error::{DecodeFailure, Error, QueryError};
+use raw::{
+    Extra, Hash, MessageType, Node, QueryArgs, QueryType, RawValue, ScrapeBloomFilter,
+    TransactionId, Want,
+};
+use version::ClientVersion;
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ping {
-    transaction_id: u16,
+    transaction_id: TransactionId,
     sender_id: Hash,
+    read_only: bool,
+    version: Option<ClientVersion>,
 }
 
 impl Ping {
-    pub fn new<T: Into<Hash>>(transaction_id: u16, sender_id: T) -> Self {
+    /// ```
+    /// use krpc_message::{Message, Ping};
+    ///
+    /// let ping = Ping::new(24929u16, b"abcdefghij0123456789");
+    /// let bencode = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe";
+    ///
+    /// assert_eq!(ping.clone().encode().unwrap(), bencode);
+    /// assert_eq!(Message::Ping(ping), Message::decode(bencode).unwrap());
+    /// ```
+    pub fn new<I, T>(transaction_id: I, sender_id: T) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+    {
         Ping {
-            transaction_id,
+            transaction_id: transaction_id.into(),
             sender_id: sender_id.into(),
+            read_only: false,
+            version: None,
         }
     }
 
-    pub fn encode(self) -> Result<Vec<u8>, bendy::encoding::Error> {
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+
+    pub fn sender_id(&self) -> &Hash {
+        &self.sender_id
+    }
+
+    pub fn reply<T: Into<Hash>>(&self, my_id: T) -> Response {
+        Response::new(self.transaction_id.clone(), my_id)
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
         raw::Message {
             transaction_id: self.transaction_id,
             msg_type: MessageType::Query,
             query_type: Some(QueryType::Ping),
-            query_args: Some(QueryArgs {
-                sender_id: self.sender_id,
-                target: None,
-                info_hash: None,
-                implied_port: None,
-                port: None,
-                token: None,
-            }),
+            query_args: Some(QueryArgs::new(self.sender_id)),
             response: None,
             error: None,
+            ip: None,
+            read_only: self.read_only,
+            version: self.version,
+            extra: Extra::new(),
         }
         .to_bencode()
+        .map_err(Error::from)
     }
 
-    fn from_raw_msg(rm: raw::Message) -> Result<Self, bendy::decoding::Error> {
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
         let a = rm.query_args.ok_or(missing!("a"))?;
         Ok(Ping {
             transaction_id: rm.transaction_id,
             sender_id: a.sender_id,
+            read_only: rm.read_only,
+            version: rm.version,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FindNode {
-    transaction_id: u16,
+    transaction_id: TransactionId,
     sender_id: Hash,
+    read_only: bool,
+    version: Option<ClientVersion>,
     target: Hash,
+    want: Option<Vec<Want>>,
 }
 
 impl FindNode {
-    pub fn new<T, B>(transaction_id: u16, sender_id: T, target: B) -> Self
+    /// ```
+    /// use krpc_message::{FindNode, Message};
+    ///
+    /// let find_node = FindNode::new(24929u16, b"abcdefghij0123456789", b"mnopqrstuvwxyz123456");
+    /// let bencode = b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q9:find_node1:t2:aa1:y1:qe";
+    ///
+    /// assert_eq!(find_node.clone().encode().unwrap(), bencode);
+    /// assert_eq!(Message::FindNode(find_node), Message::decode(bencode).unwrap());
+    /// ```
+    pub fn new<I, T, B>(transaction_id: I, sender_id: T, target: B) -> Self
     where
+        I: Into<TransactionId>,
         T: Into<Hash>,
         B: Into<Hash>,
     {
         FindNode {
-            transaction_id,
+            transaction_id: transaction_id.into(),
             sender_id: sender_id.into(),
+            read_only: false,
+            version: None,
             target: target.into(),
+            want: None,
         }
     }
 
-    pub fn encode(self) -> Result<Vec<u8>, bendy::encoding::Error> {
+    pub fn with_want(mut self, want: Vec<Want>) -> Self {
+        self.want = Some(want);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+
+    pub fn sender_id(&self) -> &Hash {
+        &self.sender_id
+    }
+
+    pub fn target(&self) -> &Hash {
+        &self.target
+    }
+
+    pub fn want(&self) -> Option<&[Want]> {
+        self.want.as_deref()
+    }
+
+    /// IPv4 nodes go in `nodes`, IPv6 ones in `nodes6`.
+    pub fn reply<T: Into<Hash>>(&self, my_id: T, nodes: Vec<Node>) -> Response {
+        Response::new(self.transaction_id.clone(), my_id).with_nodes(nodes)
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
         raw::Message {
             transaction_id: self.transaction_id,
             msg_type: MessageType::Query,
             query_type: Some(QueryType::FindNone),
             query_args: Some(QueryArgs {
-                sender_id: self.sender_id,
                 target: Some(self.target),
-                info_hash: None,
-                implied_port: None,
-                port: None,
-                token: None,
+                want: self.want,
+                ..QueryArgs::new(self.sender_id)
             }),
             response: None,
             error: None,
+            ip: None,
+            read_only: self.read_only,
+            version: self.version,
+            extra: Extra::new(),
         }
         .to_bencode()
+        .map_err(Error::from)
     }
 
-    fn from_raw_msg(rm: raw::Message) -> Result<Self, bendy::decoding::Error> {
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
         let a = rm.query_args.ok_or(missing!("a"))?;
         Ok(FindNode {
             transaction_id: rm.transaction_id,
             sender_id: a.sender_id,
-            target: a.target.ok_or(missing!("target"))?,
+            read_only: rm.read_only,
+            version: rm.version,
+            target: a.target.ok_or(missing!("a.target"))?,
+            want: a.want,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetPeers {
-    transaction_id: u16,
+    transaction_id: TransactionId,
     sender_id: Hash,
+    read_only: bool,
+    version: Option<ClientVersion>,
     info_hash: Hash,
+    want: Option<Vec<Want>>,
+    scrape: Option<bool>,
+    noseed: Option<bool>,
 }
 
 impl GetPeers {
-    pub fn encode(self) -> Result<Vec<u8>, bendy::encoding::Error> {
+    /// ```
+    /// use krpc_message::{raw::Want, GetPeers, Message};
+    ///
+    /// let get_peers = GetPeers::new(24929u16, b"abcdefghij0123456789", b"mnopqrstuvwxyz123456")
+    ///     .with_want(vec![Want::N4, Want::N6]);
+    /// let bencode = b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234564:wantl2:n42:n6ee1:q9:get_peers1:t2:aa1:y1:qe";
+    ///
+    /// assert_eq!(get_peers.clone().encode().unwrap(), bencode);
+    /// assert_eq!(Message::GetPeers(get_peers), Message::decode(bencode).unwrap());
+    /// ```
+    pub fn new<I, T, B>(transaction_id: I, sender_id: T, info_hash: B) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+        B: Into<Hash>,
+    {
+        GetPeers {
+            transaction_id: transaction_id.into(),
+            sender_id: sender_id.into(),
+            read_only: false,
+            version: None,
+            info_hash: info_hash.into(),
+            want: None,
+            scrape: None,
+            noseed: None,
+        }
+    }
+
+    pub fn with_want(mut self, want: Vec<Want>) -> Self {
+        self.want = Some(want);
+        self
+    }
+
+    /// Ask for BEP 33 `BFsd`/`BFpe` bloom filters in the response.
+    pub fn with_scrape(mut self, scrape: bool) -> Self {
+        self.scrape = Some(scrape);
+        self
+    }
+
+    /// Ask for downloaders only in `values`.
+    pub fn with_noseed(mut self, noseed: bool) -> Self {
+        self.noseed = Some(noseed);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+
+    pub fn sender_id(&self) -> &Hash {
+        &self.sender_id
+    }
+
+    pub fn info_hash(&self) -> &Hash {
+        &self.info_hash
+    }
+
+    pub fn want(&self) -> Option<&[Want]> {
+        self.want.as_deref()
+    }
+
+    pub fn scrape(&self) -> bool {
+        self.scrape.unwrap_or(false)
+    }
+
+    pub fn noseed(&self) -> bool {
+        self.noseed.unwrap_or(false)
+    }
+
+    pub fn reply_peers<T: Into<Hash>>(
+        &self,
+        my_id: T,
+        token: Vec<u8>,
+        peers: Vec<SocketAddr>,
+    ) -> Response {
+        Response {
+            token: Some(token),
+            values: Some(peers),
+            ..Response::new(self.transaction_id.clone(), my_id)
+        }
+    }
+
+    /// IPv4 nodes go in `nodes`, IPv6 ones in `nodes6`.
+    pub fn reply_nodes<T: Into<Hash>>(
+        &self,
+        my_id: T,
+        token: Vec<u8>,
+        nodes: Vec<Node>,
+    ) -> Response {
+        Response {
+            token: Some(token),
+            ..Response::new(self.transaction_id.clone(), my_id).with_nodes(nodes)
+        }
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
         raw::Message {
             transaction_id: self.transaction_id,
             msg_type: MessageType::Query,
             query_type: Some(QueryType::GetPeers),
             query_args: Some(QueryArgs {
-                sender_id: self.sender_id,
-                target: None,
                 info_hash: Some(self.info_hash),
-                implied_port: None,
-                port: None,
-                token: None,
+                want: self.want,
+                scrape: self.scrape,
+                noseed: self.noseed,
+                ..QueryArgs::new(self.sender_id)
             }),
             response: None,
             error: None,
+            ip: None,
+            read_only: self.read_only,
+            version: self.version,
+            extra: Extra::new(),
         }
         .to_bencode()
+        .map_err(Error::from)
     }
 
-    fn from_raw_msg(rm: raw::Message) -> Result<Self, bendy::decoding::Error> {
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
         let a = rm.query_args.ok_or(missing!("a"))?;
         Ok(GetPeers {
             transaction_id: rm.transaction_id,
             sender_id: a.sender_id,
-            info_hash: a.info_hash.ok_or(missing!("info_hash"))?,
+            read_only: rm.read_only,
+            version: rm.version,
+            info_hash: a.info_hash.ok_or(missing!("a.info_hash"))?,
+            want: a.want,
+            scrape: a.scrape,
+            noseed: a.noseed,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnnouncePeer {
-    transaction_id: u16,
+    transaction_id: TransactionId,
     sender_id: Hash,
+    read_only: bool,
+    version: Option<ClientVersion>,
     info_hash: Hash,
     implied_port: Option<bool>,
     port: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::hex"))]
     token: Vec<u8>,
 }
 
 impl AnnouncePeer {
-    pub fn encode(self) -> Result<Vec<u8>, bendy::encoding::Error> {
+    /// ```
+    /// use krpc_message::{AnnouncePeer, Message};
+    ///
+    /// let announce = AnnouncePeer::new(
+    ///     24929u16,
+    ///     b"abcdefghij0123456789",
+    ///     b"mnopqrstuvwxyz123456",
+    ///     6881,
+    ///     b"aoeusnth".to_vec(),
+    /// )
+    /// .with_implied_port(true);
+    /// let bencode = b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe";
+    ///
+    /// assert_eq!(announce.clone().encode().unwrap(), bencode);
+    /// assert_eq!(
+    ///     announce.peer_addr("1.2.3.4:51413".parse().unwrap()),
+    ///     "1.2.3.4:51413".parse().unwrap()
+    /// );
+    /// assert_eq!(Message::AnnouncePeer(announce), Message::decode(bencode).unwrap());
+    /// ```
+    pub fn new<I, T, B>(
+        transaction_id: I,
+        sender_id: T,
+        info_hash: B,
+        port: u16,
+        token: Vec<u8>,
+    ) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+        B: Into<Hash>,
+    {
+        AnnouncePeer {
+            transaction_id: transaction_id.into(),
+            sender_id: sender_id.into(),
+            read_only: false,
+            version: None,
+            info_hash: info_hash.into(),
+            implied_port: None,
+            port,
+            token,
+        }
+    }
+
+    /// Ask the receiver to use the UDP source port instead of `port`.
+    pub fn with_implied_port(mut self, implied_port: bool) -> Self {
+        self.implied_port = Some(implied_port);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+
+    pub fn sender_id(&self) -> &Hash {
+        &self.sender_id
+    }
+
+    pub fn info_hash(&self) -> &Hash {
+        &self.info_hash
+    }
+
+    pub fn implied_port(&self) -> bool {
+        self.implied_port.unwrap_or(false)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn token(&self) -> &[u8] {
+        &self.token
+    }
+
+    /// Address of the announced peer, given the address the query came from.
+    /// With `implied_port` set the source port is used instead of `port`.
+    pub fn peer_addr(&self, from: SocketAddr) -> SocketAddr {
+        if self.implied_port() {
+            from
+        } else {
+            SocketAddr::new(from.ip(), self.port)
+        }
+    }
+
+    pub fn reply<T: Into<Hash>>(&self, my_id: T) -> Response {
+        Response::new(self.transaction_id.clone(), my_id)
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
         raw::Message {
             transaction_id: self.transaction_id,
             msg_type: MessageType::Query,
             query_type: Some(QueryType::AnnouncePeer),
             query_args: Some(QueryArgs {
-                sender_id: self.sender_id,
-                target: None,
                 info_hash: Some(self.info_hash),
                 implied_port: self.implied_port,
                 port: Some(self.port),
                 token: Some(self.token),
+                ..QueryArgs::new(self.sender_id)
             }),
             response: None,
             error: None,
+            ip: None,
+            read_only: self.read_only,
+            version: self.version,
+            extra: Extra::new(),
         }
         .to_bencode()
+        .map_err(Error::from)
     }
 
-    fn from_raw_msg(rm: raw::Message) -> Result<Self, bendy::decoding::Error> {
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
         let a = rm.query_args.ok_or(missing!("a"))?;
         Ok(AnnouncePeer {
             transaction_id: rm.transaction_id,
             sender_id: a.sender_id,
-            info_hash: a.info_hash.ok_or(missing!("info_hash"))?,
+            read_only: rm.read_only,
+            version: rm.version,
+            info_hash: a.info_hash.ok_or(missing!("a.info_hash"))?,
             implied_port: a.implied_port,
-            port: a.port.ok_or(missing!("port"))?,
-            token: a.token.ok_or(missing!("token"))?,
+            port: a.port.ok_or(missing!("a.port"))?,
+            token: a.token.ok_or(missing!("a.token"))?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Get {
+    transaction_id: TransactionId,
+    sender_id: Hash,
+    read_only: bool,
+    version: Option<ClientVersion>,
+    target: Hash,
+    seq: Option<i64>,
+}
+
+impl Get {
+    pub fn new<I, T, B>(transaction_id: I, sender_id: T, target: B) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+        B: Into<Hash>,
+    {
+        Get {
+            transaction_id: transaction_id.into(),
+            sender_id: sender_id.into(),
+            read_only: false,
+            version: None,
+            target: target.into(),
+            seq: None,
+        }
+    }
+
+    /// Only ask for a mutable item newer than `seq`.
+    pub fn with_seq(mut self, seq: i64) -> Self {
+        self.seq = Some(seq);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+
+    pub fn sender_id(&self) -> &Hash {
+        &self.sender_id
+    }
+
+    pub fn target(&self) -> &Hash {
+        &self.target
+    }
+
+    pub fn seq(&self) -> Option<i64> {
+        self.seq
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
+        raw::Message {
+            transaction_id: self.transaction_id,
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::Get),
+            query_args: Some(QueryArgs {
+                target: Some(self.target),
+                seq: self.seq,
+                ..QueryArgs::new(self.sender_id)
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: self.read_only,
+            version: self.version,
+            extra: Extra::new(),
+        }
+        .to_bencode()
+        .map_err(Error::from)
+    }
+
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
+        let a = rm.query_args.ok_or(missing!("a"))?;
+        Ok(Get {
+            transaction_id: rm.transaction_id,
+            sender_id: a.sender_id,
+            read_only: rm.read_only,
+            version: rm.version,
+            target: a.target.ok_or(missing!("a.target"))?,
+            seq: a.seq,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct Error {
-    pub transaction_id: u16,
-    pub code: i64,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Put {
+    transaction_id: TransactionId,
+    sender_id: Hash,
+    read_only: bool,
+    version: Option<ClientVersion>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::hex"))]
+    token: Vec<u8>,
+    value: RawValue,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    key: Option<[u8; 32]>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    signature: Option<[u8; 64]>,
+    seq: Option<i64>,
+    cas: Option<i64>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    salt: Option<Vec<u8>>,
+}
+
+impl Put {
+    /// Store an immutable item, its target is the SHA1 of `value`.
+    pub fn new<I, T>(transaction_id: I, sender_id: T, token: Vec<u8>, value: RawValue) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+    {
+        Put {
+            transaction_id: transaction_id.into(),
+            sender_id: sender_id.into(),
+            read_only: false,
+            version: None,
+            token,
+            value,
+            key: None,
+            signature: None,
+            seq: None,
+            cas: None,
+            salt: None,
+        }
+    }
+
+    /// Store a mutable item signed by `key`.
+    pub fn new_mutable<I, T>(
+        transaction_id: I,
+        sender_id: T,
+        token: Vec<u8>,
+        value: RawValue,
+        key: [u8; 32],
+        signature: [u8; 64],
+        seq: i64,
+    ) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+    {
+        Put {
+            key: Some(key),
+            signature: Some(signature),
+            seq: Some(seq),
+            ..Put::new(transaction_id, sender_id, token, value)
+        }
+    }
+
+    pub fn with_salt(mut self, salt: Vec<u8>) -> Self {
+        self.salt = Some(salt);
+        self
+    }
+
+    /// Only overwrite the item if its current `seq` equals `cas`.
+    pub fn with_cas(mut self, cas: i64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+
+    pub fn sender_id(&self) -> &Hash {
+        &self.sender_id
+    }
+
+    pub fn token(&self) -> &[u8] {
+        &self.token
+    }
+
+    pub fn value(&self) -> &RawValue {
+        &self.value
+    }
+
+    pub fn key(&self) -> Option<&[u8; 32]> {
+        self.key.as_ref()
+    }
+
+    pub fn signature(&self) -> Option<&[u8; 64]> {
+        self.signature.as_ref()
+    }
+
+    pub fn seq(&self) -> Option<i64> {
+        self.seq
+    }
+
+    pub fn cas(&self) -> Option<i64> {
+        self.cas
+    }
+
+    pub fn salt(&self) -> Option<&[u8]> {
+        self.salt.as_deref()
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
+        raw::Message {
+            transaction_id: self.transaction_id,
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::Put),
+            query_args: Some(QueryArgs {
+                token: Some(self.token),
+                value: Some(self.value),
+                key: self.key,
+                signature: self.signature,
+                seq: self.seq,
+                cas: self.cas,
+                salt: self.salt,
+                ..QueryArgs::new(self.sender_id)
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: self.read_only,
+            version: self.version,
+            extra: Extra::new(),
+        }
+        .to_bencode()
+        .map_err(Error::from)
+    }
+
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
+        let a = rm.query_args.ok_or(missing!("a"))?;
+        Ok(Put {
+            transaction_id: rm.transaction_id,
+            sender_id: a.sender_id,
+            read_only: rm.read_only,
+            version: rm.version,
+            token: a.token.ok_or(missing!("a.token"))?,
+            value: a.value.ok_or(missing!("a.v"))?,
+            key: a.key,
+            signature: a.signature,
+            seq: a.seq,
+            cas: a.cas,
+            salt: a.salt,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SampleInfohashes {
+    transaction_id: TransactionId,
+    sender_id: Hash,
+    read_only: bool,
+    version: Option<ClientVersion>,
+    target: Hash,
+}
+
+impl SampleInfohashes {
+    pub fn new<I, T, B>(transaction_id: I, sender_id: T, target: B) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+        B: Into<Hash>,
+    {
+        SampleInfohashes {
+            transaction_id: transaction_id.into(),
+            sender_id: sender_id.into(),
+            read_only: false,
+            version: None,
+            target: target.into(),
+        }
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&ClientVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        &self.transaction_id
+    }
+
+    pub fn sender_id(&self) -> &Hash {
+        &self.sender_id
+    }
+
+    pub fn target(&self) -> &Hash {
+        &self.target
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
+        raw::Message {
+            transaction_id: self.transaction_id,
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::SampleInfohashes),
+            query_args: Some(QueryArgs {
+                target: Some(self.target),
+                ..QueryArgs::new(self.sender_id)
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: self.read_only,
+            version: self.version,
+            extra: Extra::new(),
+        }
+        .to_bencode()
+        .map_err(Error::from)
+    }
+
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
+        let a = rm.query_args.ok_or(missing!("a"))?;
+        Ok(SampleInfohashes {
+            transaction_id: rm.transaction_id,
+            sender_id: a.sender_id,
+            read_only: rm.read_only,
+            version: rm.version,
+            target: a.target.ok_or(missing!("a.target"))?,
+        })
+    }
+}
+
+/// KRPC error codes from BEP 5 and BEP 44.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "i64", into = "i64"))]
+pub enum ErrorCode {
+    Generic,
+    Server,
+    Protocol,
+    MethodUnknown,
+    /// `v` is over 1000 bytes.
+    MessageTooBig,
+    InvalidSignature,
+    /// `salt` is over 64 bytes.
+    SaltTooBig,
+    CasMismatch,
+    SequenceLessThanCurrent,
+    /// A code without its own variant. Codes are compared by number, so
+    /// `Other(201)` equals `Generic`.
+    Other(i64),
+}
+
+impl PartialEq for ErrorCode {
+    fn eq(&self, other: &Self) -> bool {
+        i64::from(*self) == i64::from(*other)
+    }
+}
+
+impl Eq for ErrorCode {}
+
+impl From<i64> for ErrorCode {
+    fn from(code: i64) -> Self {
+        match code {
+            201 => Self::Generic,
+            202 => Self::Server,
+            203 => Self::Protocol,
+            204 => Self::MethodUnknown,
+            205 => Self::MessageTooBig,
+            206 => Self::InvalidSignature,
+            207 => Self::SaltTooBig,
+            301 => Self::CasMismatch,
+            302 => Self::SequenceLessThanCurrent,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for i64 {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Generic => 201,
+            ErrorCode::Server => 202,
+            ErrorCode::Protocol => 203,
+            ErrorCode::MethodUnknown => 204,
+            ErrorCode::MessageTooBig => 205,
+            ErrorCode::InvalidSignature => 206,
+            ErrorCode::SaltTooBig => 207,
+            ErrorCode::CasMismatch => 301,
+            ErrorCode::SequenceLessThanCurrent => 302,
+            ErrorCode::Other(code) => code,
+        }
+    }
+}
+
+/// A KRPC error message (`y` = `e`). Before the crate [`Error`] enum this
+/// struct was called `Error`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorMessage {
+    pub transaction_id: TransactionId,
+    pub code: ErrorCode,
     pub message: String,
+    pub version: Option<ClientVersion>,
 }
 
-impl Error {
-    pub fn encode(self) -> Result<Vec<u8>, bendy::encoding::Error> {
+impl ErrorMessage {
+    pub fn new<I: Into<TransactionId>, M: Into<String>>(
+        transaction_id: I,
+        code: ErrorCode,
+        message: M,
+    ) -> Self {
+        ErrorMessage {
+            transaction_id: transaction_id.into(),
+            code,
+            message: message.into(),
+            version: None,
+        }
+    }
+
+    pub fn generic<I: Into<TransactionId>, M: Into<String>>(transaction_id: I, message: M) -> Self {
+        Self::new(transaction_id, ErrorCode::Generic, message)
+    }
+
+    pub fn server<I: Into<TransactionId>, M: Into<String>>(transaction_id: I, message: M) -> Self {
+        Self::new(transaction_id, ErrorCode::Server, message)
+    }
+
+    pub fn protocol<I: Into<TransactionId>, M: Into<String>>(
+        transaction_id: I,
+        message: M,
+    ) -> Self {
+        Self::new(transaction_id, ErrorCode::Protocol, message)
+    }
+
+    pub fn method_unknown<I: Into<TransactionId>>(transaction_id: I) -> Self {
+        Self::new(transaction_id, ErrorCode::MethodUnknown, "Method Unknown")
+    }
+
+    pub fn with_version(mut self, version: ClientVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
         raw::Message {
             transaction_id: self.transaction_id,
             msg_type: MessageType::Error,
@@ -194,34 +1087,105 @@ impl Error {
             query_args: None,
             response: None,
             error: Some(raw::Error {
-                code: self.code,
+                code: self.code.into(),
                 message: self.message,
+                extra: Vec::new(),
             }),
+            ip: None,
+            read_only: false,
+            version: self.version,
+            extra: Extra::new(),
         }
         .to_bencode()
+        .map_err(Error::from)
     }
 
-    fn from_raw_msg(rm: raw::Message) -> Result<Self, bendy::decoding::Error> {
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
         let e = rm.error.ok_or(missing!("e"))?;
-        Ok(Error {
+        Ok(ErrorMessage {
             transaction_id: rm.transaction_id,
-            code: e.code,
+            code: e.code.into(),
             message: e.message,
+            version: rm.version,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Response {
-    pub transaction_id: u16,
+    pub transaction_id: TransactionId,
     pub sender_id: Hash,
     pub nodes: Option<Vec<Node>>,
-    pub values: Option<Vec<SocketAddrV4>>,
+    pub nodes6: Option<Vec<Node>>,
+    pub values: Option<Vec<SocketAddr>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
     pub token: Option<Vec<u8>>,
+    pub value: Option<RawValue>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    pub key: Option<[u8; 32]>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    pub signature: Option<[u8; 64]>,
+    pub seq: Option<i64>,
+    pub interval: Option<i64>,
+    pub num: Option<i64>,
+    pub samples: Option<Vec<Hash>>,
+    pub seeds_filter: Option<ScrapeBloomFilter>,
+    pub peers_filter: Option<ScrapeBloomFilter>,
+    /// Our external address as seen by the responder (BEP 42).
+    pub ip: Option<SocketAddr>,
+    pub version: Option<ClientVersion>,
 }
 
 impl Response {
-    pub fn encode(self) -> Result<Vec<u8>, bendy::encoding::Error> {
+    pub fn new<I, T>(transaction_id: I, sender_id: T) -> Self
+    where
+        I: Into<TransactionId>,
+        T: Into<Hash>,
+    {
+        Response {
+            transaction_id: transaction_id.into(),
+            sender_id: sender_id.into(),
+            nodes: None,
+            nodes6: None,
+            values: None,
+            token: None,
+            value: None,
+            key: None,
+            signature: None,
+            seq: None,
+            interval: None,
+            num: None,
+            samples: None,
+            seeds_filter: None,
+            peers_filter: None,
+            ip: None,
+            version: None,
+        }
+    }
+
+    /// Split `nodes` by address family into `nodes` and `nodes6`.
+    pub fn with_nodes(mut self, nodes: Vec<Node>) -> Self {
+        let (nodes, nodes6): (Vec<_>, Vec<_>) = nodes.into_iter().partition(|n| n.addr.is_ipv4());
+        if !nodes6.is_empty() {
+            self.nodes6 = Some(nodes6);
+        }
+        if !nodes.is_empty() || self.nodes6.is_none() {
+            self.nodes = Some(nodes);
+        }
+        self
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
         raw::Message {
             transaction_id: self.transaction_id,
             msg_type: MessageType::Response,
@@ -230,39 +1194,82 @@ impl Response {
             response: Some(raw::Response {
                 sender_id: self.sender_id,
                 nodes: self.nodes,
+                nodes6: self.nodes6,
                 values: self.values,
                 token: self.token,
+                value: self.value,
+                key: self.key,
+                signature: self.signature,
+                seq: self.seq,
+                interval: self.interval,
+                num: self.num,
+                samples: self.samples,
+                seeds_filter: self.seeds_filter,
+                peers_filter: self.peers_filter,
+                extra: Extra::new(),
             }),
             error: None,
+            ip: self.ip,
+            read_only: false,
+            version: self.version,
+            extra: Extra::new(),
         }
         .to_bencode()
+        .map_err(Error::from)
     }
 
-    fn from_raw_msg(rm: raw::Message) -> Result<Self, bendy::decoding::Error> {
+    fn from_raw_msg(rm: raw::Message) -> Result<Self, Error> {
         let r = rm.response.ok_or(missing!("r"))?;
         Ok(Response {
             transaction_id: rm.transaction_id,
             sender_id: r.sender_id,
             nodes: r.nodes,
+            nodes6: r.nodes6,
             values: r.values,
             token: r.token,
+            value: r.value,
+            key: r.key,
+            signature: r.signature,
+            seq: r.seq,
+            interval: r.interval,
+            num: r.num,
+            samples: r.samples,
+            seeds_filter: r.seeds_filter,
+            peers_filter: r.peers_filter,
+            ip: rm.ip,
+            version: rm.version,
         })
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Message {
     Ping(Ping),
     FindNode(FindNode),
     GetPeers(GetPeers),
     AnnouncePeer(AnnouncePeer),
+    Get(Get),
+    Put(Put),
+    SampleInfohashes(SampleInfohashes),
     Response(Response),
-    Error(Error),
+    Error(ErrorMessage),
 }
 
 impl Message {
-    pub fn decode(bytes: &[u8]) -> Result<Self, bendy::decoding::Error> {
+    /// Keys this crate doesn't know are dropped, so encoding a decoded message
+    /// need not give back the same bytes. To forward packets unchanged, decode
+    /// them with [`raw::Message::from_bencode_lossless`] and encode that instead.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        #[cfg(feature = "fast-decode")]
+        let rm = fast::decode(bytes)?;
+        #[cfg(not(feature = "fast-decode"))]
         let rm = raw::Message::from_bencode(bytes)?;
+        Self::from_raw(rm)
+    }
+
+    pub(crate) fn from_raw(rm: raw::Message) -> Result<Self, Error> {
         Ok(match rm.msg_type {
             MessageType::Query => {
                 let qt = rm.query_type.ok_or(missing!("q"))?;
@@ -273,21 +1280,130 @@ impl Message {
                     QueryType::AnnouncePeer => {
                         Message::AnnouncePeer(AnnouncePeer::from_raw_msg(rm)?)
                     }
+                    QueryType::Get => Message::Get(Get::from_raw_msg(rm)?),
+                    QueryType::Put => Message::Put(Put::from_raw_msg(rm)?),
+                    QueryType::SampleInfohashes => {
+                        Message::SampleInfohashes(SampleInfohashes::from_raw_msg(rm)?)
+                    }
                 }
             }
             MessageType::Response => Message::Response(Response::from_raw_msg(rm)?),
-            MessageType::Error => Message::Error(Error::from_raw_msg(rm)?),
+            MessageType::Error => Message::Error(ErrorMessage::from_raw_msg(rm)?),
         })
     }
 
-    pub fn encode(self) -> Result<Vec<u8>, bendy::encoding::Error> {
+    /// Like [`Message::decode`], but on failure also return the transaction ID,
+    /// message type, query and sender that could be read, to build an error reply.
+    pub fn decode_lenient(bytes: &[u8]) -> Result<Self, Box<DecodeFailure>> {
+        Self::decode(bytes).map_err(|e| Box::new(DecodeFailure::recover(bytes, e)))
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
         match self {
             Self::Ping(p) => p.encode(),
             Self::FindNode(f) => f.encode(),
             Self::GetPeers(g) => g.encode(),
             Self::AnnouncePeer(a) => a.encode(),
+            Self::Get(g) => g.encode(),
+            Self::Put(p) => p.encode(),
+            Self::SampleInfohashes(s) => s.encode(),
             Self::Response(r) => r.encode(),
             Self::Error(e) => e.encode(),
         }
     }
+
+    /// Client identifier of the sender, if it sent one.
+    pub fn version(&self) -> Option<&ClientVersion> {
+        match self {
+            Self::Ping(p) => p.version(),
+            Self::FindNode(f) => f.version(),
+            Self::GetPeers(g) => g.version(),
+            Self::AnnouncePeer(a) => a.version(),
+            Self::Get(g) => g.version(),
+            Self::Put(p) => p.version(),
+            Self::SampleInfohashes(s) => s.version(),
+            Self::Response(r) => r.version.as_ref(),
+            Self::Error(e) => e.version.as_ref(),
+        }
+    }
+
+    pub fn transaction_id(&self) -> &TransactionId {
+        match self {
+            Self::Ping(p) => p.transaction_id(),
+            Self::FindNode(f) => f.transaction_id(),
+            Self::GetPeers(g) => g.transaction_id(),
+            Self::AnnouncePeer(a) => a.transaction_id(),
+            Self::Get(g) => g.transaction_id(),
+            Self::Put(p) => p.transaction_id(),
+            Self::SampleInfohashes(s) => s.transaction_id(),
+            Self::Response(r) => &r.transaction_id,
+            Self::Error(e) => &e.transaction_id,
+        }
+    }
+
+    /// E.g. to send a query under an ID from a
+    /// [`TransactionManager`](transaction::TransactionManager).
+    pub fn set_transaction_id(&mut self, transaction_id: TransactionId) {
+        match self {
+            Self::Ping(p) => p.transaction_id = transaction_id,
+            Self::FindNode(f) => f.transaction_id = transaction_id,
+            Self::GetPeers(g) => g.transaction_id = transaction_id,
+            Self::AnnouncePeer(a) => a.transaction_id = transaction_id,
+            Self::Get(g) => g.transaction_id = transaction_id,
+            Self::Put(p) => p.transaction_id = transaction_id,
+            Self::SampleInfohashes(s) => s.transaction_id = transaction_id,
+            Self::Response(r) => r.transaction_id = transaction_id,
+            Self::Error(e) => e.transaction_id = transaction_id,
+        }
+    }
+
+    /// `None` for responses and errors.
+    pub fn query_type(&self) -> Option<QueryType> {
+        Some(match self {
+            Self::Ping(_) => QueryType::Ping,
+            Self::FindNode(_) => QueryType::FindNone,
+            Self::GetPeers(_) => QueryType::GetPeers,
+            Self::AnnouncePeer(_) => QueryType::AnnouncePeer,
+            Self::Get(_) => QueryType::Get,
+            Self::Put(_) => QueryType::Put,
+            Self::SampleInfohashes(_) => QueryType::SampleInfohashes,
+            Self::Response(_) | Self::Error(_) => return None,
+        })
+    }
+
+    /// The `target` or `info_hash` of a query.
+    pub fn query_target(&self) -> Option<Hash> {
+        match self {
+            Self::FindNode(f) => Some(f.target.clone()),
+            Self::GetPeers(g) => Some(g.info_hash.clone()),
+            Self::AnnouncePeer(a) => Some(a.info_hash.clone()),
+            Self::Get(g) => Some(g.target.clone()),
+            Self::SampleInfohashes(s) => Some(s.target.clone()),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_from_for_message {
+    ($($variant:ident($t:ty)),*) => {
+        $(
+            impl From<$t> for Message {
+                fn from(m: $t) -> Self {
+                    Message::$variant(m)
+                }
+            }
+        )*
+    };
 }
+
+impl_from_for_message!(
+    Ping(Ping),
+    FindNode(FindNode),
+    GetPeers(GetPeers),
+    AnnouncePeer(AnnouncePeer),
+    Get(Get),
+    Put(Put),
+    SampleInfohashes(SampleInfohashes),
+    Response(Response),
+    Error(ErrorMessage)
+);
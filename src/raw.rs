@@ -1,37 +1,21 @@
 use std::{
-    fmt::{self, Debug, Display},
-    net::{IpAddr, SocketAddr, SocketAddrV4}, ops::Deref,
+    borrow::Borrow,
+    collections::{btree_map, BTreeMap},
+    fmt::{self, Debug},
+    iter::Peekable,
+    net::{IpAddr, SocketAddr},
+    ops::Deref,
 };
 
 use bendy::{
-    decoding::{FromBencode, Object, ResultExt},
-    encoding::{AsString, SingleItemEncoder, ToBencode},
+    decoding::{Decoder, FromBencode, Object},
+    encoding::{AsString, PrintableInteger, SingleItemEncoder, SortedDictEncoder, ToBencode},
 };
 
+use crate::error::{invalid_length, invalid_value, missing, ResultExt};
+pub use crate::{bep33::ScrapeBloomFilter, version::ClientVersion};
 pub use bendy::encoding;
 
-#[derive(Debug)]
-pub struct MalformedError<T: Display>(pub T);
-impl<T: Display + Debug> std::error::Error for MalformedError<T> {}
-impl<T: Display> Display for MalformedError<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-macro_rules! malformed {
-    ($m:expr) => {
-        bendy::decoding::Error::malformed_content(MalformedError($m))
-    };
-}
-macro_rules! missing {
-    ($m:expr) => {
-        bendy::decoding::Error::missing_field($m)
-    };
-}
-
-pub(crate) use malformed;
-pub(crate) use missing;
-
 #[derive(PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
 pub struct Hash {
     pub bytes: [u8; 20],
@@ -42,9 +26,7 @@ impl FromBencode for Hash {
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
         let s = object.try_into_bytes()?;
         Ok(Hash {
-            bytes: s
-                .try_into()
-                .map_err(|_| malformed!("expected 20 bytes str"))?,
+            bytes: s.try_into().map_err(|_| invalid_length!(s.len()))?,
         })
     }
 }
@@ -78,7 +60,293 @@ impl From<&[u8; 20]> for Hash {
     }
 }
 
+/// Transaction ID (`t`), clients use anything from 1 to 8 bytes or more.
+#[derive(PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub struct TransactionId {
+    pub bytes: Vec<u8>,
+}
+
+impl FromBencode for TransactionId {
+    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        Ok(object.try_into_bytes()?.into())
+    }
+}
+
+impl ToBencode for TransactionId {
+    const MAX_DEPTH: usize = 0;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
+        encoder.emit_bytes(&self.bytes)
+    }
+}
+
+impl fmt::Debug for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.bytes {
+            write!(f, "{:02x}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lets maps keyed by ID be searched with plain bytes.
+impl Borrow<[u8]> for TransactionId {
+    fn borrow(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Deref for TransactionId {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        &self.bytes
+    }
+}
+
+impl From<u16> for TransactionId {
+    fn from(id: u16) -> Self {
+        TransactionId {
+            bytes: id.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl From<u32> for TransactionId {
+    fn from(id: u32) -> Self {
+        TransactionId {
+            bytes: id.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl From<&[u8]> for TransactionId {
+    fn from(bytes: &[u8]) -> Self {
+        TransactionId {
+            bytes: bytes.to_vec(),
+        }
+    }
+}
+
+impl<const N: usize> From<&[u8; N]> for TransactionId {
+    fn from(bytes: &[u8; N]) -> Self {
+        TransactionId {
+            bytes: bytes.to_vec(),
+        }
+    }
+}
+
+impl TryFrom<&TransactionId> for u16 {
+    type Error = crate::Error;
+    fn try_from(id: &TransactionId) -> Result<Self, Self::Error> {
+        let bytes = id.bytes[..].try_into();
+        Ok(u16::from_be_bytes(
+            bytes.map_err(|_| invalid_length!(id.len()).context("t"))?,
+        ))
+    }
+}
+
+impl TryFrom<&TransactionId> for u32 {
+    type Error = crate::Error;
+    fn try_from(id: &TransactionId) -> Result<Self, Self::Error> {
+        let bytes = id.bytes[..].try_into();
+        Ok(u32::from_be_bytes(
+            bytes.map_err(|_| invalid_length!(id.len()).context("t"))?,
+        ))
+    }
+}
+
+/// Bencoded value kept as its exact encoding, BEP 44 signatures cover these bytes.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RawValue {
+    pub bytes: Vec<u8>,
+}
+
+impl RawValue {
+    pub fn new<T: ToBencode>(value: &T) -> Result<Self, bendy::encoding::Error> {
+        Ok(RawValue {
+            bytes: value.to_bencode()?,
+        })
+    }
+
+    /// Check that `bytes` hold exactly one bencoded value.
+    pub(crate) fn check(&self) -> Result<(), bendy::decoding::Error> {
+        let mut decoder = Decoder::new(&self.bytes).with_max_depth(Self::MAX_DEPTH);
+        match decoder.next_object()?.ok_or(missing!("value"))? {
+            Object::List(l) => drop(l.into_raw()?),
+            Object::Dict(d) => drop(d.into_raw()?),
+            Object::Bytes(_) | Object::Integer(_) => {}
+        }
+        if decoder.next_object()?.is_some() {
+            return Err(invalid_value!("must be a single item").into());
+        }
+        Ok(())
+    }
+}
+
+impl FromBencode for RawValue {
+    const EXPECTED_RECURSION_DEPTH: usize = 32;
+    fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        let bytes = match object {
+            Object::Bytes(b) => {
+                let mut bytes = format!("{}:", b.len()).into_bytes();
+                bytes.extend_from_slice(b);
+                bytes
+            }
+            Object::Integer(i) => format!("i{}e", i).into_bytes(),
+            Object::List(l) => l.into_raw()?.to_vec(),
+            Object::Dict(d) => d.into_raw()?.to_vec(),
+        };
+        Ok(RawValue { bytes })
+    }
+}
+
+impl ToBencode for RawValue {
+    const MAX_DEPTH: usize = 32;
+
+    /// Writes `bytes` back unchanged, failing if they aren't a single bencoded value.
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
+        use bendy::encoding::Error as E;
+        self.check().map_err(E::malformed_content)?;
+        let mut decoder = Decoder::new(&self.bytes).with_max_depth(Self::MAX_DEPTH);
+        let object = decoder
+            .next_object()
+            .and_then(|object| object.ok_or_else(|| missing!("value").into()))
+            .map_err(E::malformed_content)?;
+        emit_verbatim(object, encoder)
+    }
+}
+
+/// Integer digits written back as they were read, even beyond `i64`.
+struct IntText<'a>(&'a str);
+
+impl fmt::Display for IntText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl PrintableInteger for IntText<'_> {}
+
+/// Emit a decoded object token by token, which reproduces its input since
+/// bendy only accepts canonical bencode.
+fn emit_verbatim(object: Object, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
+    use bendy::encoding::Error as E;
+    match object {
+        Object::Bytes(b) => encoder.emit_bytes(b),
+        Object::Integer(i) => encoder.emit_int(IntText(i)),
+        Object::List(mut list) => encoder.emit_list(|e| {
+            while let Some(item) = list.next_object().map_err(E::malformed_content)? {
+                e.emit_with(|e| emit_verbatim(item, e))?;
+            }
+            Ok(())
+        }),
+        Object::Dict(mut dict) => encoder.emit_dict(|mut e| {
+            while let Some((key, value)) = dict.next_pair().map_err(E::malformed_content)? {
+                e.emit_pair_with(key, |e| emit_verbatim(value, e))?;
+            }
+            Ok(())
+        }),
+    }
+}
+
+/// Keys kept by [`Message::from_bencode_lossless`] because they are unknown or
+/// don't fit their field, re-emitted in sorted order.
+pub type Extra = BTreeMap<Vec<u8>, RawValue>;
+
+/// Decode the pairs of a dict with `field`, which returns whether it knows the
+/// key. In lossless mode a pair `field` doesn't know or can't decode is kept
+/// in `extra` as it is.
+fn decode_fields(
+    object: Object,
+    lossless: bool,
+    extra: &mut Extra,
+    mut field: impl FnMut(&[u8], Object, &mut Extra) -> Result<bool, crate::Error>,
+) -> Result<(), crate::Error> {
+    let mut dict = object.try_into_dictionary()?;
+    while let Some((key, value)) = dict.next_pair()? {
+        if !lossless {
+            field(key, value, extra)?;
+            continue;
+        }
+        let raw = RawValue::decode_bencode_object(value).context(String::from_utf8_lossy(key))?;
+        let known = {
+            let mut decoder = Decoder::new(&raw.bytes);
+            let value = decoder.next_object()?.ok_or(missing!("value"))?;
+            field(key, value, extra)
+        };
+        if !matches!(known, Ok(true)) {
+            extra.insert(key.to_vec(), raw);
+        }
+    }
+    Ok(())
+}
+
+/// Integers a flag that is present encodes back to.
+pub(crate) const FLAG_VALUES: &[&str] = &["0", "1"];
+/// `ro` is left out when false, so only `1` comes back.
+pub(crate) const RO_VALUES: &[&str] = &["1"];
+
+/// Interpret the integer of a 0/1 flag. In lossless mode an integer the flag
+/// wouldn't encode back to is kept in `extra` instead and the flag stays unset.
+pub(crate) fn flag(
+    i: &str,
+    key: &[u8],
+    values: &[&str],
+    lossless: bool,
+    extra: &mut Extra,
+) -> Option<bool> {
+    if lossless && !values.contains(&i) {
+        let bytes = format!("i{}e", i).into_bytes();
+        extra.insert(key.to_vec(), RawValue { bytes });
+        return None;
+    }
+    Some(i == "1")
+}
+
+/// Sorted dict encoder that interleaves the extra keys with the known ones.
+struct ExtraDictEncoder<'a, 'e> {
+    encoder: SortedDictEncoder<'e>,
+    extra: Peekable<btree_map::Iter<'a, Vec<u8>, RawValue>>,
+}
+
+impl<'a, 'e> ExtraDictEncoder<'a, 'e> {
+    fn new(encoder: SortedDictEncoder<'e>, extra: &'a Extra) -> Self {
+        ExtraDictEncoder {
+            encoder,
+            extra: extra.iter().peekable(),
+        }
+    }
+
+    fn emit_extra(&mut self, before: Option<&[u8]>) -> Result<(), bendy::encoding::Error> {
+        while let Some((key, value)) = self
+            .extra
+            .next_if(|(key, _)| before.is_none_or(|before| key.as_slice() < before))
+        {
+            self.encoder.emit_pair(key, value)?;
+        }
+        Ok(())
+    }
+
+    fn emit_pair<E: ToBencode>(
+        &mut self,
+        key: &[u8],
+        value: E,
+    ) -> Result<(), bendy::encoding::Error> {
+        self.emit_extra(Some(key))?;
+        // a field that is set takes the place of an extra value kept for its key
+        self.extra
+            .next_if(|(extra_key, _)| extra_key.as_slice() == key);
+        self.encoder.emit_pair(key, value)
+    }
+
+    fn finish(mut self) -> Result<(), bendy::encoding::Error> {
+        self.emit_extra(None)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum MessageType {
     Query,
     Response,
@@ -88,14 +356,17 @@ pub enum MessageType {
 impl FromBencode for MessageType {
     const EXPECTED_RECURSION_DEPTH: usize = 0;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
-        let s = object.try_into_bytes()?;
+        Ok(Self::from_name(object.try_into_bytes()?)?)
+    }
+}
+
+impl MessageType {
+    pub(crate) fn from_name(s: &[u8]) -> Result<Self, crate::Error> {
         Ok(match s {
             b"q" => Self::Query,
             b"r" => Self::Response,
             b"e" => Self::Error,
-            _ => {
-                return Err(malformed!("'y' must be q, r, or e"));
-            }
+            _ => return Err(crate::Error::UnknownMessageType { name: s.to_vec() }),
         })
     }
 }
@@ -113,73 +384,204 @@ impl ToBencode for MessageType {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum QueryType {
     Ping,
+    #[cfg_attr(feature = "serde", serde(rename = "find_node"))]
     FindNone,
     GetPeers,
     AnnouncePeer,
+    Get,
+    Put,
+    SampleInfohashes,
 }
 
 impl FromBencode for QueryType {
     const EXPECTED_RECURSION_DEPTH: usize = 0;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
-        let s = object.try_into_bytes()?;
+        Ok(Self::from_name(object.try_into_bytes()?)?)
+    }
+}
+
+impl QueryType {
+    pub(crate) fn from_name(s: &[u8]) -> Result<Self, crate::Error> {
         Ok(match s {
             b"ping" => Self::Ping,
             b"find_node" => Self::FindNone,
             b"get_peers" => Self::GetPeers,
             b"announce_peer" => Self::AnnouncePeer,
-            _ => {
-                return Err(malformed!("'q' must be one of 4 query types"));
-            }
+            b"get" => Self::Get,
+            b"put" => Self::Put,
+            b"sample_infohashes" => Self::SampleInfohashes,
+            _ => return Err(crate::Error::UnknownQuery { name: s.to_vec() }),
         })
     }
+
+    /// The `q` value on the wire.
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Self::Ping => b"ping",
+            Self::FindNone => b"find_node",
+            Self::GetPeers => b"get_peers",
+            Self::AnnouncePeer => b"announce_peer",
+            Self::Get => b"get",
+            Self::Put => b"put",
+            Self::SampleInfohashes => b"sample_infohashes",
+        }
+    }
 }
 
 impl ToBencode for QueryType {
     const MAX_DEPTH: usize = 0;
 
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
+        encoder.emit_bytes(self.name())
+    }
+}
+
+/// Address families requested via the BEP 32 `want` key.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Want {
+    N4,
+    N6,
+}
+
+impl FromBencode for Want {
+    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        Ok(Self::from_name(object.try_into_bytes()?)?)
+    }
+}
+
+impl Want {
+    pub(crate) fn from_name(s: &[u8]) -> Result<Self, crate::Error> {
+        Ok(match s {
+            b"n4" => Self::N4,
+            b"n6" => Self::N6,
+            _ => return Err(invalid_value!("must be n4 or n6")),
+        })
+    }
+}
+
+impl ToBencode for Want {
+    const MAX_DEPTH: usize = 0;
+
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
         encoder.emit_bytes(match self {
-            Self::Ping => b"ping",
-            Self::FindNone => b"find_node",
-            Self::GetPeers => b"get_peers",
-            Self::AnnouncePeer => b"announce_peer",
+            Self::N4 => b"n4",
+            Self::N6 => b"n6",
         })
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QueryArgs {
     pub sender_id: Hash, // id
     pub target: Option<Hash>,
     pub info_hash: Option<Hash>,
     pub implied_port: Option<bool>,
     pub port: Option<u16>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
     pub token: Option<Vec<u8>>,
+    pub want: Option<Vec<Want>>,
+    pub value: Option<RawValue>, // v
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    pub key: Option<[u8; 32]>, // k
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    pub signature: Option<[u8; 64]>, // sig
+    pub seq: Option<i64>,
+    pub cas: Option<i64>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    pub salt: Option<Vec<u8>>,
+    pub scrape: Option<bool>,
+    pub noseed: Option<bool>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Extra::is_empty",
+            with = "crate::serde_impls::extra"
+        )
+    )]
+    pub extra: Extra,
+}
+
+impl QueryArgs {
+    pub fn new(sender_id: Hash) -> Self {
+        QueryArgs {
+            sender_id,
+            target: None,
+            info_hash: None,
+            implied_port: None,
+            port: None,
+            token: None,
+            want: None,
+            value: None,
+            key: None,
+            signature: None,
+            seq: None,
+            cas: None,
+            salt: None,
+            scrape: None,
+            noseed: None,
+            extra: Extra::new(),
+        }
+    }
 }
 
 impl FromBencode for QueryArgs {
-    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    const EXPECTED_RECURSION_DEPTH: usize = 1 + RawValue::EXPECTED_RECURSION_DEPTH;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        Ok(Self::decode_object(object, false)?)
+    }
+}
+
+impl QueryArgs {
+    fn decode_object(object: Object, lossless: bool) -> Result<Self, crate::Error> {
+        let mut extra = Extra::new();
         let mut sender_id = None;
         let mut target = None;
         let mut info_hash = None;
         let mut implied_port = None;
         let mut port = None;
         let mut token = None;
+        let mut want = None;
+        let mut value = None;
+        let mut key = None;
+        let mut signature = None;
+        let mut seq = None;
+        let mut cas = None;
+        let mut salt = None;
+        let mut scrape = None;
+        let mut noseed = None;
 
-        let mut dict = object.try_into_dictionary()?;
-        while let Some(pair) = dict.next_pair()? {
-            match pair {
+        decode_fields(object, lossless, &mut extra, |name, object, extra| {
+            match (name, object) {
+                (b"cas", v) => {
+                    cas = i64::decode_bencode_object(v).context("cas").map(Some)?;
+                }
                 (b"id", value) => {
                     sender_id = Hash::decode_bencode_object(value).context("id").map(Some)?;
                 }
                 (b"implied_port", value) => {
-                    implied_port = value
-                        .try_into_integer()
-                        .context("implied_port")
-                        .map(|i| Some(i == "1"))?;
+                    let i = value.try_into_integer().context("implied_port")?;
+                    implied_port = flag(i, b"implied_port", FLAG_VALUES, lossless, extra);
                 }
                 (b"info_hash", value) => {
                     info_hash = Hash::decode_bencode_object(value)
@@ -191,7 +593,8 @@ impl FromBencode for QueryArgs {
                         .try_into_integer()
                         .context("port")?
                         .parse::<u16>()
-                        .map_err(|_| malformed!("must be valid integer"))
+                        .map_err(|_| invalid_value!("must be a valid port"))
+                        .context("port")
                         .map(Some)?;
                 }
                 (b"target", value) => {
@@ -204,10 +607,41 @@ impl FromBencode for QueryArgs {
                         .context("token")
                         .map(|i| Some(i.0))?;
                 }
-                _ => continue,
+                (b"want", value) => {
+                    want = Vec::<Want>::decode_bencode_object(value)
+                        .context("want")
+                        .map(Some)?;
+                }
+                (b"k", v) => {
+                    key = decode_array(v).context("k").map(Some)?;
+                }
+                (b"noseed", v) => {
+                    let i = v.try_into_integer().context("noseed")?;
+                    noseed = flag(i, b"noseed", FLAG_VALUES, lossless, extra);
+                }
+                (b"scrape", v) => {
+                    let i = v.try_into_integer().context("scrape")?;
+                    scrape = flag(i, b"scrape", FLAG_VALUES, lossless, extra);
+                }
+                (b"salt", v) => {
+                    salt = AsString::decode_bencode_object(v)
+                        .context("salt")
+                        .map(|i| Some(i.0))?;
+                }
+                (b"seq", v) => {
+                    seq = i64::decode_bencode_object(v).context("seq").map(Some)?;
+                }
+                (b"sig", v) => {
+                    signature = decode_array(v).context("sig").map(Some)?;
+                }
+                (b"v", v) => {
+                    value = RawValue::decode_bencode_object(v).context("v").map(Some)?;
+                }
+                _ => return Ok(false),
             }
-        }
-        let sender_id = sender_id.ok_or(missing!("sender_id"))?;
+            Ok(true)
+        })?;
+        let sender_id = sender_id.ok_or(missing!("id"))?;
         Ok(QueryArgs {
             sender_id,
             target,
@@ -215,15 +649,29 @@ impl FromBencode for QueryArgs {
             implied_port,
             port,
             token,
+            want,
+            value,
+            key,
+            signature,
+            seq,
+            cas,
+            salt,
+            scrape,
+            noseed,
+            extra,
         })
     }
 }
 
 impl ToBencode for QueryArgs {
-    const MAX_DEPTH: usize = 0;
+    const MAX_DEPTH: usize = 1 + RawValue::MAX_DEPTH;
 
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
-        encoder.emit_dict(|mut e| {
+        encoder.emit_dict(|e| {
+            let mut e = ExtraDictEncoder::new(e, &self.extra);
+            if let Some(cas) = &self.cas {
+                e.emit_pair(b"cas", cas)?;
+            }
             e.emit_pair(b"id", &self.sender_id)?;
             if let Some(implied_port) = &self.implied_port {
                 e.emit_pair(b"implied_port", *implied_port as u8)?;
@@ -231,188 +679,377 @@ impl ToBencode for QueryArgs {
             if let Some(info_hash) = &self.info_hash {
                 e.emit_pair(b"info_hash", info_hash)?;
             }
+            if let Some(key) = &self.key {
+                e.emit_pair(b"k", AsString(key))?;
+            }
+            if let Some(noseed) = &self.noseed {
+                e.emit_pair(b"noseed", *noseed as u8)?;
+            }
             if let Some(port) = &self.port {
                 e.emit_pair(b"port", port)?;
             }
+            if let Some(salt) = &self.salt {
+                e.emit_pair(b"salt", AsString(salt))?;
+            }
+            if let Some(scrape) = &self.scrape {
+                e.emit_pair(b"scrape", *scrape as u8)?;
+            }
+            if let Some(seq) = &self.seq {
+                e.emit_pair(b"seq", seq)?;
+            }
+            if let Some(signature) = &self.signature {
+                e.emit_pair(b"sig", AsString(signature))?;
+            }
             if let Some(target) = &self.target {
                 e.emit_pair(b"target", target)?;
             }
             if let Some(token) = &self.token {
                 e.emit_pair(b"token", AsString(token))?;
             }
-            Ok(())
+            if let Some(value) = &self.value {
+                e.emit_pair(b"v", value)?;
+            }
+            if let Some(want) = &self.want {
+                e.emit_pair(b"want", want)?;
+            }
+            e.finish()
         })
     }
 }
 
-struct SocketAddrV4Wrap<T>(T);
+pub(crate) struct SocketAddrWrap<T>(T);
 
-impl TryFrom<&[u8]> for SocketAddrV4Wrap<SocketAddrV4> {
+impl TryFrom<&[u8]> for SocketAddrWrap<SocketAddr> {
     type Error = ();
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        if bytes.len() != 6 {
-            return Err(());
-        }
-        let (ip, port) = bytes.split_at(4);
-        let ip = IpAddr::from(<[u8; 4]>::try_from(ip).unwrap());
+        let (ip, port) = match bytes.len() {
+            6 => {
+                let (ip, port) = bytes.split_at(4);
+                (IpAddr::from(<[u8; 4]>::try_from(ip).unwrap()), port)
+            }
+            18 => {
+                let (ip, port) = bytes.split_at(16);
+                (IpAddr::from(<[u8; 16]>::try_from(ip).unwrap()), port)
+            }
+            _ => return Err(()),
+        };
         let port = u16::from_be_bytes(<[u8; 2]>::try_from(port).unwrap());
-        Ok(match SocketAddr::from((ip, port)) {
-            SocketAddr::V4(a) => SocketAddrV4Wrap(a),
-            _ => unreachable!(),
-        })
-    }
-}
-
-impl From<&SocketAddrV4Wrap<&SocketAddrV4>> for [u8; 6] {
-    fn from(addr: &SocketAddrV4Wrap<&SocketAddrV4>) -> Self {
-        let mut bytes = [0u8; 6];
-        bytes[0..4].copy_from_slice(&addr.0.ip().octets());
-        bytes[4..6].copy_from_slice(&addr.0.port().to_be_bytes());
-        bytes
+        Ok(SocketAddrWrap(SocketAddr::from((ip, port))))
     }
 }
 
-/*impl From<SocketAddrV4Wrap<&SocketAddrV4>> for [u8; 6] {
-    fn from(addr: SocketAddrV4Wrap<&SocketAddrV4>) -> Self {
-        let mut bytes = [0u8; 6];
-        bytes[0..4].copy_from_slice(&addr.0.ip().octets());
-        bytes[4..6].copy_from_slice(&addr.0.port().to_be_bytes());
-        bytes
-    }
-}*/
-
-impl From<SocketAddrV4Wrap<SocketAddrV4>> for [u8; 6] {
-    fn from(addr: SocketAddrV4Wrap<SocketAddrV4>) -> Self {
-        let mut bytes = [0u8; 6];
-        bytes[0..4].copy_from_slice(&addr.0.ip().octets());
-        bytes[4..6].copy_from_slice(&addr.0.port().to_be_bytes());
-        bytes
+impl SocketAddrWrap<&SocketAddr> {
+    fn extend_compact(&self, bytes: &mut Vec<u8>) {
+        match self.0 {
+            SocketAddr::V4(a) => bytes.extend_from_slice(&a.ip().octets()),
+            SocketAddr::V6(a) => bytes.extend_from_slice(&a.ip().octets()),
+        }
+        bytes.extend_from_slice(&self.0.port().to_be_bytes());
     }
 }
 
-impl FromBencode for SocketAddrV4Wrap<SocketAddrV4> {
+impl FromBencode for SocketAddrWrap<SocketAddr> {
     const EXPECTED_RECURSION_DEPTH: usize = 0;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
         let bytes = object.try_into_bytes()?;
-        SocketAddrV4Wrap::try_from(bytes).map_err(|_| malformed!(" SocketAddrV4 must be 6 bytes"))
+        Ok(SocketAddrWrap::try_from(bytes).map_err(|_| invalid_length!(bytes.len()))?)
     }
 }
 
-impl ToBencode for SocketAddrV4Wrap<&SocketAddrV4> {
+impl ToBencode for SocketAddrWrap<&SocketAddr> {
     const MAX_DEPTH: usize = 0;
 
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
-        encoder.emit_bytes(&Into::<[u8; 6]>::into(self))
+        let mut bytes = Vec::with_capacity(18);
+        self.extend_compact(&mut bytes);
+        encoder.emit_bytes(&bytes)
     }
 }
 
-impl From<SocketAddrV4Wrap<SocketAddrV4>> for SocketAddrV4 {
-    fn from(wrap: SocketAddrV4Wrap<SocketAddrV4>) -> Self {
+impl From<SocketAddrWrap<SocketAddr>> for SocketAddr {
+    fn from(wrap: SocketAddrWrap<SocketAddr>) -> Self {
         wrap.0
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Node {
     pub id: Hash,
-    pub addr: SocketAddrV4,
+    pub addr: SocketAddr,
 }
 
-impl From<[u8; 26]> for Node {
-    fn from(bytes: [u8; 26]) -> Self {
+impl Node {
+    /// Length of the compact node info: 26 bytes for IPv4, 38 bytes for IPv6.
+    pub fn compact_len(&self) -> usize {
+        match self.addr {
+            SocketAddr::V4(_) => 26,
+            SocketAddr::V6(_) => 38,
+        }
+    }
+
+    fn extend_compact(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.id.bytes);
+        SocketAddrWrap(&self.addr).extend_compact(bytes);
+    }
+}
+
+impl TryFrom<&[u8]> for Node {
+    type Error = ();
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != 26 && bytes.len() != 38 {
+            return Err(());
+        }
         let (id, addr) = bytes.split_at(20);
-        Node {
+        Ok(Node {
             id: Hash {
                 bytes: id.try_into().unwrap(),
             },
-            addr: SocketAddrV4Wrap::try_from(addr).unwrap().into(),
-        }
+            addr: SocketAddrWrap::try_from(addr)?.into(),
+        })
+    }
+}
+
+impl From<[u8; 26]> for Node {
+    fn from(bytes: [u8; 26]) -> Self {
+        Node::try_from(&bytes[..]).unwrap()
     }
 }
 
-impl From<&Node> for [u8; 26] {
-    fn from(node: &Node) -> Self {
-        let mut bytes = [0u8; 26];
-        bytes[0..20].copy_from_slice(&node.id.bytes);
-        bytes[20..26].copy_from_slice(&Into::<[u8; 6]>::into(SocketAddrV4Wrap(node.addr)));
-        bytes
+impl From<[u8; 38]> for Node {
+    fn from(bytes: [u8; 38]) -> Self {
+        Node::try_from(&bytes[..]).unwrap()
     }
 }
 
-impl From<(Hash, SocketAddrV4)> for Node {
-    fn from(pair: (Hash, SocketAddrV4)) -> Self {
+impl TryFrom<&Node> for [u8; 26] {
+    type Error = ();
+    fn try_from(node: &Node) -> Result<Self, Self::Error> {
+        let mut bytes = Vec::with_capacity(26);
+        node.extend_compact(&mut bytes);
+        bytes.try_into().map_err(|_| ())
+    }
+}
+
+impl TryFrom<&Node> for [u8; 38] {
+    type Error = ();
+    fn try_from(node: &Node) -> Result<Self, Self::Error> {
+        let mut bytes = Vec::with_capacity(38);
+        node.extend_compact(&mut bytes);
+        bytes.try_into().map_err(|_| ())
+    }
+}
+
+impl From<(Hash, SocketAddr)> for Node {
+    fn from(pair: (Hash, SocketAddr)) -> Self {
         let (id, addr) = pair;
         Node { id, addr }
     }
 }
 
-struct VecNodeWrap<T>(T);
+fn decode_array<const N: usize>(object: Object) -> Result<[u8; N], bendy::decoding::Error> {
+    let bytes = object.try_into_bytes()?;
+    Ok(bytes.try_into().map_err(|_| invalid_length!(bytes.len()))?)
+}
+
+/// Concatenated compact node infos, `LEN` is 26 for `nodes` and 38 for `nodes6`.
+struct VecNodeWrap<T, const LEN: usize>(T);
 
-impl FromBencode for VecNodeWrap<Vec<Node>> {
+impl<const LEN: usize> FromBencode for VecNodeWrap<Vec<Node>, LEN> {
     const EXPECTED_RECURSION_DEPTH: usize = 0;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
         let bytes = object.try_into_bytes()?;
-        let chunks = bytes.chunks(26);
+        let chunks = bytes.chunks(LEN);
         let mut v = Vec::new();
         for chunk in chunks {
-            v.push(Node::from(
-                <[u8; 26]>::try_from(chunk).map_err(|_| malformed!("node must be 26 bytes"))?,
-            ));
+            if chunk.len() != LEN {
+                return Err(invalid_length!(bytes.len()).into());
+            }
+            v.push(Node::try_from(chunk).map_err(|_| invalid_value!("invalid compact node"))?);
         }
         Ok(VecNodeWrap(v))
     }
 }
 
-impl<T> ToBencode for VecNodeWrap<T>
+impl<T, const LEN: usize> ToBencode for VecNodeWrap<T, LEN>
 where
     T: AsRef<[Node]>,
 {
     const MAX_DEPTH: usize = 0;
 
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
-        let mut bytes = Vec::new();
+        let mut bytes = Vec::with_capacity(self.0.as_ref().len() * LEN);
         for node in self.0.as_ref() {
-            bytes.extend_from_slice(&Into::<[u8; 26]>::into(node))
+            if node.compact_len() != LEN {
+                return Err(bendy::encoding::Error::malformed_content(invalid_length!(
+                    node.compact_len()
+                )));
+            }
+            node.extend_compact(&mut bytes);
         }
         encoder.emit_bytes(&bytes)
     }
 }
 
-impl From<VecNodeWrap<Vec<Node>>> for Vec<Node> {
-    fn from(wrap: VecNodeWrap<Vec<Node>>) -> Self {
+impl<const LEN: usize> From<VecNodeWrap<Vec<Node>, LEN>> for Vec<Node> {
+    fn from(wrap: VecNodeWrap<Vec<Node>, LEN>) -> Self {
+        wrap.0
+    }
+}
+
+/// Concatenated 20-byte hashes, as in BEP 51 `samples`.
+struct VecHashWrap<T>(T);
+
+impl FromBencode for VecHashWrap<Vec<Hash>> {
+    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        let bytes = object.try_into_bytes()?;
+        let chunks = bytes.chunks(20);
+        let mut v = Vec::new();
+        for chunk in chunks {
+            v.push(Hash {
+                bytes: chunk.try_into().map_err(|_| invalid_length!(bytes.len()))?,
+            });
+        }
+        Ok(VecHashWrap(v))
+    }
+}
+
+impl<T> ToBencode for VecHashWrap<T>
+where
+    T: AsRef<[Hash]>,
+{
+    const MAX_DEPTH: usize = 0;
+
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
+        let mut bytes = Vec::with_capacity(self.0.as_ref().len() * 20);
+        for hash in self.0.as_ref() {
+            bytes.extend_from_slice(&hash.bytes);
+        }
+        encoder.emit_bytes(&bytes)
+    }
+}
+
+impl From<VecHashWrap<Vec<Hash>>> for Vec<Hash> {
+    fn from(wrap: VecHashWrap<Vec<Hash>>) -> Self {
         wrap.0
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Response {
     pub sender_id: Hash, // id
     pub nodes: Option<Vec<Node>>,
-    pub values: Option<Vec<SocketAddrV4>>,
+    pub nodes6: Option<Vec<Node>>,
+    pub values: Option<Vec<SocketAddr>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
     pub token: Option<Vec<u8>>,
+    pub value: Option<RawValue>, // v
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    pub key: Option<[u8; 32]>, // k
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_impls::hex_opt")
+    )]
+    pub signature: Option<[u8; 64]>, // sig
+    pub seq: Option<i64>,
+    pub interval: Option<i64>,
+    pub num: Option<i64>,
+    pub samples: Option<Vec<Hash>>,
+    pub seeds_filter: Option<ScrapeBloomFilter>, // BFsd
+    pub peers_filter: Option<ScrapeBloomFilter>, // BFpe
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Extra::is_empty",
+            with = "crate::serde_impls::extra"
+        )
+    )]
+    pub extra: Extra,
+}
+
+impl Response {
+    pub fn new(sender_id: Hash) -> Self {
+        Response {
+            sender_id,
+            nodes: None,
+            nodes6: None,
+            values: None,
+            token: None,
+            value: None,
+            key: None,
+            signature: None,
+            seq: None,
+            interval: None,
+            num: None,
+            samples: None,
+            seeds_filter: None,
+            peers_filter: None,
+            extra: Extra::new(),
+        }
+    }
 }
 
 impl FromBencode for Response {
-    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    const EXPECTED_RECURSION_DEPTH: usize = 1 + RawValue::EXPECTED_RECURSION_DEPTH;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        Ok(Self::decode_object(object, false)?)
+    }
+}
+
+impl Response {
+    fn decode_object(object: Object, lossless: bool) -> Result<Self, crate::Error> {
+        let mut extra = Extra::new();
         let mut sender_id = None;
         let mut nodes: Option<Vec<Node>> = None;
-        let mut values: Option<Vec<SocketAddrV4>> = None;
+        let mut nodes6: Option<Vec<Node>> = None;
+        let mut values: Option<Vec<SocketAddr>> = None;
         let mut token = None;
+        let mut value = None;
+        let mut key = None;
+        let mut signature = None;
+        let mut seq = None;
+        let mut interval = None;
+        let mut num = None;
+        let mut samples: Option<Vec<Hash>> = None;
+        let mut seeds_filter = None;
+        let mut peers_filter = None;
 
-        let mut dict = object.try_into_dictionary()?;
-        while let Some(pair) = dict.next_pair()? {
-            match pair {
+        decode_fields(object, lossless, &mut extra, |name, object, _| {
+            match (name, object) {
+                (b"BFpe", v) => {
+                    peers_filter = ScrapeBloomFilter::decode_bencode_object(v)
+                        .context("BFpe")
+                        .map(Some)?;
+                }
+                (b"BFsd", v) => {
+                    seeds_filter = ScrapeBloomFilter::decode_bencode_object(v)
+                        .context("BFsd")
+                        .map(Some)?;
+                }
                 (b"id", value) => {
                     sender_id = Hash::decode_bencode_object(value).context("id").map(Some)?;
                 }
                 (b"nodes", value) => {
-                    nodes = VecNodeWrap::decode_bencode_object(value)
+                    nodes = VecNodeWrap::<_, 26>::decode_bencode_object(value)
                         .context("nodes")
                         .map(|i| Some(i.into()))?;
                 }
+                (b"nodes6", value) => {
+                    nodes6 = VecNodeWrap::<_, 38>::decode_bencode_object(value)
+                        .context("nodes6")
+                        .map(|i| Some(i.into()))?;
+                }
                 (b"values", value) => {
-                    values = Vec::<SocketAddrV4Wrap<SocketAddrV4>>::decode_bencode_object(value)
+                    values = Vec::<SocketAddrWrap<SocketAddr>>::decode_bencode_object(value)
                         .context("values")
                         .map(|v| Some(v.into_iter().map(|i| i.into()).collect()))?;
                 }
@@ -421,103 +1058,225 @@ impl FromBencode for Response {
                         .context("token")
                         .map(|i| Some(i.0))?;
                 }
-                _ => continue,
+                (b"interval", v) => {
+                    interval = i64::decode_bencode_object(v)
+                        .context("interval")
+                        .map(Some)?;
+                }
+                (b"k", v) => {
+                    key = decode_array(v).context("k").map(Some)?;
+                }
+                (b"num", v) => {
+                    num = i64::decode_bencode_object(v).context("num").map(Some)?;
+                }
+                (b"samples", v) => {
+                    samples = VecHashWrap::decode_bencode_object(v)
+                        .context("samples")
+                        .map(|i| Some(i.into()))?;
+                }
+                (b"seq", v) => {
+                    seq = i64::decode_bencode_object(v).context("seq").map(Some)?;
+                }
+                (b"sig", v) => {
+                    signature = decode_array(v).context("sig").map(Some)?;
+                }
+                (b"v", v) => {
+                    value = RawValue::decode_bencode_object(v).context("v").map(Some)?;
+                }
+                _ => return Ok(false),
             }
-        }
-        let sender_id = sender_id.ok_or(missing!("sender_id"))?;
+            Ok(true)
+        })?;
+        let sender_id = sender_id.ok_or(missing!("id"))?;
         Ok(Response {
             sender_id,
             nodes,
+            nodes6,
             values,
             token,
+            value,
+            key,
+            signature,
+            seq,
+            interval,
+            num,
+            samples,
+            seeds_filter,
+            peers_filter,
+            extra,
         })
     }
 }
 
 impl ToBencode for Response {
-    const MAX_DEPTH: usize = 0;
+    const MAX_DEPTH: usize = 1 + RawValue::MAX_DEPTH;
 
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
-        encoder.emit_dict(|mut e| {
+        encoder.emit_dict(|e| {
+            let mut e = ExtraDictEncoder::new(e, &self.extra);
+            if let Some(peers_filter) = &self.peers_filter {
+                e.emit_pair(b"BFpe", peers_filter)?;
+            }
+            if let Some(seeds_filter) = &self.seeds_filter {
+                e.emit_pair(b"BFsd", seeds_filter)?;
+            }
             e.emit_pair(b"id", &self.sender_id)?;
+            if let Some(interval) = &self.interval {
+                e.emit_pair(b"interval", interval)?;
+            }
+            if let Some(key) = &self.key {
+                e.emit_pair(b"k", AsString(key))?;
+            }
 
             if let Some(nodes) = &self.nodes {
-                e.emit_pair(b"nodes", VecNodeWrap(nodes))?;
+                e.emit_pair(b"nodes", VecNodeWrap::<_, 26>(nodes))?;
+            }
+            if let Some(nodes6) = &self.nodes6 {
+                e.emit_pair(b"nodes6", VecNodeWrap::<_, 38>(nodes6))?;
+            }
+            if let Some(num) = &self.num {
+                e.emit_pair(b"num", num)?;
+            }
+            if let Some(samples) = &self.samples {
+                e.emit_pair(b"samples", VecHashWrap(samples))?;
+            }
+            if let Some(seq) = &self.seq {
+                e.emit_pair(b"seq", seq)?;
+            }
+            if let Some(signature) = &self.signature {
+                e.emit_pair(b"sig", AsString(signature))?;
             }
             if let Some(token) = &self.token {
                 e.emit_pair(b"token", AsString(token))?;
             }
+            if let Some(value) = &self.value {
+                e.emit_pair(b"v", value)?;
+            }
             if let Some(values) = &self.values {
                 e.emit_pair(
                     b"values",
-                    values.iter().map(SocketAddrV4Wrap).collect::<Vec<_>>(),
+                    values.iter().map(SocketAddrWrap).collect::<Vec<_>>(),
                 )?;
             }
-            Ok(())
+            e.finish()
         })
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Error {
     pub code: i64,
     pub message: String,
+    /// Items after the message, kept by [`Message::from_bencode_lossless`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub extra: Vec<RawValue>,
 }
 
 impl FromBencode for Error {
-    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    const EXPECTED_RECURSION_DEPTH: usize = 1 + RawValue::EXPECTED_RECURSION_DEPTH;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        Ok(Self::decode_object(object, false)?)
+    }
+}
+
+impl Error {
+    fn decode_object(object: Object, lossless: bool) -> Result<Self, crate::Error> {
         let mut list = object.try_into_list()?;
         let code = list.next_object()?.ok_or(missing!("code"))?;
         let code = i64::decode_bencode_object(code)?;
         let message = list.next_object()?.ok_or(missing!("message"))?;
         let message = String::decode_bencode_object(message)?;
-        Ok(Error { code, message })
+        let mut extra = Vec::new();
+        while let Some(item) = list.next_object()? {
+            if lossless {
+                extra.push(RawValue::decode_bencode_object(item)?);
+            }
+        }
+        Ok(Error {
+            code,
+            message,
+            extra,
+        })
     }
 }
 
 impl ToBencode for Error {
-    const MAX_DEPTH: usize = 0;
+    const MAX_DEPTH: usize = 1 + RawValue::MAX_DEPTH;
 
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
         encoder.emit_list(|e| {
             e.emit_int(self.code)?;
-            e.emit_str(&self.message)
+            e.emit_str(&self.message)?;
+            for item in &self.extra {
+                e.emit(item)?;
+            }
+            Ok(())
         })
     }
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message {
-    pub transaction_id: u16,           // t
-    pub msg_type: MessageType,         // y
-    pub query_type: Option<QueryType>, // q
-    pub query_args: Option<QueryArgs>, // a
-    pub response: Option<Response>,    // r
-    pub error: Option<Error>,          // e
+    pub transaction_id: TransactionId,  // t
+    pub msg_type: MessageType,          // y
+    pub query_type: Option<QueryType>,  // q
+    pub query_args: Option<QueryArgs>,  // a
+    pub response: Option<Response>,     // r
+    pub error: Option<Error>,           // e
+    pub ip: Option<SocketAddr>,         // ip
+    pub read_only: bool,                // ro
+    pub version: Option<ClientVersion>, // v
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "Extra::is_empty",
+            with = "crate::serde_impls::extra"
+        )
+    )]
+    pub extra: Extra,
 }
 
 impl FromBencode for Message {
-    const EXPECTED_RECURSION_DEPTH: usize = 3;
+    const EXPECTED_RECURSION_DEPTH: usize = 2 + RawValue::EXPECTED_RECURSION_DEPTH;
     fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        Ok(Self::decode_object(object, false)?)
+    }
+}
+
+impl Message {
+    /// Decode like [`FromBencode::from_bencode`], but keep keys that are unknown or
+    /// don't fit their field in `extra` so that encoding gives back the same bytes.
+    /// An unknown `q` for instance leaves `query_type` unset, with `q` and `a` in `extra`.
+    pub fn from_bencode_lossless(bytes: &[u8]) -> Result<Self, crate::Error> {
+        let mut decoder = Decoder::new(bytes).with_max_depth(Self::EXPECTED_RECURSION_DEPTH);
+        let object = decoder.next_object()?.ok_or(missing!("message"))?;
+        Self::decode_object(object, true)
+    }
+
+    fn decode_object(object: Object, lossless: bool) -> Result<Self, crate::Error> {
+        let mut extra = Extra::new();
         let mut transaction_id = None;
         let mut msg_type = None;
         let mut query_type = None;
         let mut query_args = None;
         let mut response = None;
         let mut error = None;
+        let mut ip = None;
+        let mut read_only = false;
+        let mut version = None;
 
-        let mut dict = object.try_into_dictionary()?;
-        while let Some(pair) = dict.next_pair()? {
-            match pair {
+        decode_fields(object, lossless, &mut extra, |name, object, extra| {
+            match (name, object) {
                 (b"t", value) => {
-                    transaction_id = Some(u16::from_be_bytes(
-                        value
-                            .try_into_bytes()
-                            .context("t")?
-                            .try_into()
-                            .map_err(|_| malformed!("t must be 2 byte str"))?,
-                    ));
+                    transaction_id = TransactionId::decode_bencode_object(value)
+                        .context("t")
+                        .map(Some)?;
                 }
                 (b"y", value) => {
                     msg_type = MessageType::decode_bencode_object(value)
@@ -530,21 +1289,38 @@ impl FromBencode for Message {
                         .map(Some)?;
                 }
                 (b"a", value) => {
-                    query_args = QueryArgs::decode_bencode_object(value)
+                    query_args = QueryArgs::decode_object(value, lossless)
                         .context("a")
                         .map(Some)?;
                 }
                 (b"r", value) => {
-                    response = Response::decode_bencode_object(value)
+                    response = Response::decode_object(value, lossless)
                         .context("r")
                         .map(Some)?;
                 }
                 (b"e", value) => {
-                    error = Error::decode_bencode_object(value).context("e").map(Some)?;
+                    error = Error::decode_object(value, lossless)
+                        .context("e")
+                        .map(Some)?;
                 }
-                _ => continue,
+                (b"ip", value) => {
+                    ip = SocketAddrWrap::decode_bencode_object(value)
+                        .context("ip")
+                        .map(|i| Some(i.into()))?;
+                }
+                (b"ro", value) => {
+                    let i = value.try_into_integer().context("ro")?;
+                    read_only = flag(i, b"ro", RO_VALUES, lossless, extra).unwrap_or(false);
+                }
+                (b"v", value) => {
+                    version = ClientVersion::decode_bencode_object(value)
+                        .context("v")
+                        .map(Some)?;
+                }
+                _ => return Ok(false),
             }
-        }
+            Ok(true)
+        })?;
         let transaction_id = transaction_id.ok_or(missing!("t"))?;
         let msg_type = msg_type.ok_or(missing!("y"))?;
         Ok(Message {
@@ -554,30 +1330,44 @@ impl FromBencode for Message {
             query_args,
             response,
             error,
+            ip,
+            read_only,
+            version,
+            extra,
         })
     }
 }
 
 impl ToBencode for Message {
-    const MAX_DEPTH: usize = 3;
+    const MAX_DEPTH: usize = 2 + RawValue::MAX_DEPTH;
 
     fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
-        encoder.emit_dict(|mut e| {
+        encoder.emit_dict(|e| {
+            let mut e = ExtraDictEncoder::new(e, &self.extra);
             if let Some(query_args) = &self.query_args {
                 e.emit_pair(b"a", query_args)?;
             }
             if let Some(error) = &self.error {
                 e.emit_pair(b"e", error)?;
             }
+            if let Some(ip) = &self.ip {
+                e.emit_pair(b"ip", SocketAddrWrap(ip))?;
+            }
             if let Some(query_type) = &self.query_type {
                 e.emit_pair(b"q", query_type)?;
             }
             if let Some(response) = &self.response {
                 e.emit_pair(b"r", response)?;
             }
-            e.emit_pair(b"t", AsString(self.transaction_id.to_be_bytes()))?;
+            if self.read_only {
+                e.emit_pair(b"ro", 1)?;
+            }
+            e.emit_pair(b"t", &self.transaction_id)?;
+            if let Some(version) = &self.version {
+                e.emit_pair(b"v", version)?;
+            }
             e.emit_pair(b"y", &self.msg_type)?;
-            Ok(())
+            e.finish()
         })
     }
 }
@@ -1,20 +1,31 @@
 use bendy::{decoding::FromBencode, encoding::ToBencode};
 
-use crate::raw::{Error, Hash, Message, MessageType, Node, QueryArgs, QueryType, Response};
+use crate::raw::{
+    ClientVersion, Error, Extra, Message, MessageType, QueryArgs, QueryType, RawValue, Response,
+    ScrapeBloomFilter, TransactionId, Want,
+};
 
 fn ser_deser(bytes: &[u8], msg: Message) {
+    #[cfg(feature = "fast-decode")]
+    assert_eq!(crate::fast::decode(bytes).unwrap(), msg);
     let m = Message::from_bencode(bytes).unwrap();
     assert_eq!(m, msg);
     let b = m.to_bencode().unwrap();
     assert_eq!(b, bytes);
 }
 
+fn assert_invalid(bytes: &[u8]) {
+    #[cfg(feature = "fast-decode")]
+    assert!(crate::fast::decode(bytes).is_err());
+    assert!(Message::from_bencode(bytes).is_err());
+}
+
 #[test]
 fn test() {
     let ping = (
         b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
         Message {
-            transaction_id: 24929,
+            transaction_id: 24929u16.into(),
             msg_type: MessageType::Query,
             query_type: Some(QueryType::Ping),
             query_args: Some(QueryArgs {
@@ -24,9 +35,23 @@ fn test() {
                 implied_port: None,
                 port: None,
                 token: None,
+                want: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
             }),
             response: None,
             error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
         },
     );
     ser_deser(ping.0, ping.1);
@@ -34,7 +59,7 @@ fn test() {
     let find_node = (
         b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q9:find_node1:t2:aa1:y1:qe", 
         Message {
-            transaction_id: 24929,
+            transaction_id: 24929u16.into(),
             msg_type: MessageType::Query,
             query_type: Some(QueryType::FindNone),
             query_args: Some(QueryArgs {
@@ -44,9 +69,23 @@ fn test() {
                 implied_port: None,
                 port: None,
                 token: None,
+                want: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
             }),
             response: None,
             error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
         }
     );
     ser_deser(find_node.0, find_node.1);
@@ -54,7 +93,7 @@ fn test() {
     let get_peers = (
         b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe",
         Message {
-            transaction_id: 24929,
+            transaction_id: 24929u16.into(),
             msg_type: MessageType::Query,
             query_type: Some(QueryType::GetPeers),
             query_args: Some(QueryArgs {
@@ -64,9 +103,23 @@ fn test() {
                 implied_port: None,
                 port: None,
                 token: None,
+                want: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
             }),
             response: None,
             error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
         }
     );
     ser_deser(get_peers.0, get_peers.1);
@@ -74,7 +127,7 @@ fn test() {
     let announce_peer = (
         b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
         Message {
-            transaction_id: 24929,
+            transaction_id: 24929u16.into(),
             msg_type: MessageType::Query,
             query_type: Some(QueryType::AnnouncePeer),
             query_args: Some(QueryArgs {
@@ -84,9 +137,23 @@ fn test() {
                 implied_port: Some(true),
                 port: Some(6881),
                 token: Some(b"aoeusnth".to_vec()),
+                want: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
             }),
             response: None,
             error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
         }
     );
     ser_deser(announce_peer.0, announce_peer.1);
@@ -94,19 +161,34 @@ fn test() {
     let response1 = (
         b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:ABCDaa6:EFGHaaee1:t2:aa1:y1:re",
         Message {
-            transaction_id: 24929,
+            transaction_id: 24929u16.into(),
             msg_type: MessageType::Response,
             query_type: None,
             query_args: None,
             response: Some(Response {
                 sender_id: b"abcdefghij0123456789".into(), 
                 nodes: None,
+                nodes6: None,
                 values: Some(vec![
                     "65.66.67.68:24929".parse().unwrap(), 
                     "69.70.71.72:24929".parse().unwrap()]), 
-                token: Some(b"aoeusnth".to_vec()) 
+                token: Some(b"aoeusnth".to_vec()),
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                interval: None,
+                num: None,
+                samples: None,
+                seeds_filter: None,
+                peers_filter: None,
+                extra: Extra::new(),
             }),
             error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
         }
     );
     ser_deser(response1.0, response1.1);
@@ -114,7 +196,7 @@ fn test() {
     let response2 = (
         b"d1:rd2:id20:abcdefghij01234567895:nodes52:mnopqrstuvwxyz123456ABCDaa11111111111111111111EFGHaa5:token8:aoeusnthe1:t2:aa1:y1:re",
         Message {
-            transaction_id: 24929,
+            transaction_id: 24929u16.into(),
             msg_type: MessageType::Response,
             query_type: None,
             query_args: None,
@@ -123,10 +205,25 @@ fn test() {
                 nodes: Some(vec![
                     (b"mnopqrstuvwxyz123456".into(), "65.66.67.68:24929".parse().unwrap()).into(),
                     (b"11111111111111111111".into(), "69.70.71.72:24929".parse().unwrap()).into()]), 
+                nodes6: None,
                 values: None,
-                token: Some(b"aoeusnth".to_vec()) 
+                token: Some(b"aoeusnth".to_vec()),
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                interval: None,
+                num: None,
+                samples: None,
+                seeds_filter: None,
+                peers_filter: None,
+                extra: Extra::new(),
             }),
             error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
         }
     );
     ser_deser(response2.0, response2.1);
@@ -134,7 +231,508 @@ fn test() {
     let error = (
         b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee",
         Message {
-            transaction_id: 24929,
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Error,
+            query_type: None,
+            query_args: None,
+            response: None,
+            error: Some(Error {
+                code: 201,
+                message: "A Generic Error Ocurred".to_string(),
+                extra: Vec::new(),
+            }),
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(error.0, error.1);
+}
+
+#[test]
+fn test_ipv6() {
+    let find_node = (
+        b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz1234564:wantl2:n42:n6ee1:q9:find_node1:t2:aa1:y1:qe",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::FindNone),
+            query_args: Some(QueryArgs {
+                sender_id: b"abcdefghij0123456789".into(),
+                target: Some(b"mnopqrstuvwxyz123456".into()),
+                info_hash: None,
+                implied_port: None,
+                port: None,
+                token: None,
+                want: Some(vec![Want::N4, Want::N6]),
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(find_node.0, find_node.1);
+
+    let response_values = (
+        b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:ABCDaa18:ABCDEFGHIJKLMNOPaaee1:t2:aa1:y1:re",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Response,
+            query_type: None,
+            query_args: None,
+            response: Some(Response {
+                sender_id: b"abcdefghij0123456789".into(),
+                nodes: None,
+                nodes6: None,
+                values: Some(vec![
+                    "65.66.67.68:24929".parse().unwrap(),
+                    "[4142:4344:4546:4748:494a:4b4c:4d4e:4f50]:24929".parse().unwrap(),
+                ]),
+                token: Some(b"aoeusnth".to_vec()),
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                interval: None,
+                num: None,
+                samples: None,
+                seeds_filter: None,
+                peers_filter: None,
+                extra: Extra::new(),
+            }),
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(response_values.0, response_values.1);
+
+    let response_nodes = (
+        b"d1:rd2:id20:abcdefghij01234567895:nodes26:mnopqrstuvwxyz123456ABCDaa6:nodes638:11111111111111111111ABCDEFGHIJKLMNOPaae1:t2:aa1:y1:re",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Response,
+            query_type: None,
+            query_args: None,
+            response: Some(Response {
+                sender_id: b"abcdefghij0123456789".into(),
+                nodes: Some(vec![(
+                    b"mnopqrstuvwxyz123456".into(),
+                    "65.66.67.68:24929".parse().unwrap(),
+                )
+                    .into()]),
+                nodes6: Some(vec![(
+                    b"11111111111111111111".into(),
+                    "[4142:4344:4546:4748:494a:4b4c:4d4e:4f50]:24929"
+                        .parse()
+                        .unwrap(),
+                )
+                    .into()]),
+                values: None,
+                token: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                interval: None,
+                num: None,
+                samples: None,
+                seeds_filter: None,
+                peers_filter: None,
+                extra: Extra::new(),
+            }),
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(response_nodes.0, response_nodes.1);
+
+    // an IPv6 node must not end up in the IPv4 `nodes` key
+    let mut bad = Message::from_bencode(response_nodes.0).unwrap();
+    let r = bad.response.as_mut().unwrap();
+    r.nodes = r.nodes6.take();
+    assert!(bad.to_bencode().is_err());
+
+    assert_invalid(
+        b"d1:rd2:id20:abcdefghij01234567896:nodes626:mnopqrstuvwxyz123456ABCDaae1:t2:aa1:y1:re",
+    );
+}
+
+#[test]
+fn test_get_put() {
+    let get = (
+        b"d1:ad2:id20:abcdefghij01234567893:seqi4e6:target20:mnopqrstuvwxyz123456e1:q3:get1:t2:aa1:y1:qe",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::Get),
+            query_args: Some(QueryArgs {
+                sender_id: b"abcdefghij0123456789".into(),
+                target: Some(b"mnopqrstuvwxyz123456".into()),
+                info_hash: None,
+                implied_port: None,
+                port: None,
+                token: None,
+                want: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: Some(4),
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(get.0, get.1);
+
+    let put_immutable = (
+        b"d1:ad2:id20:abcdefghij01234567895:token8:aoeusnth1:v12:Hello World!e1:q3:put1:t2:aa1:y1:qe",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::Put),
+            query_args: Some(QueryArgs {
+                sender_id: b"abcdefghij0123456789".into(),
+                target: None,
+                info_hash: None,
+                implied_port: None,
+                port: None,
+                token: Some(b"aoeusnth".to_vec()),
+                want: None,
+                value: Some(RawValue {
+                    bytes: b"12:Hello World!".to_vec(),
+                }),
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(put_immutable.0, put_immutable.1);
+
+    let put_mutable = (
+        b"d1:ad3:casi3e2:id20:abcdefghij01234567891:k32:kkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkk4:salt6:foobar3:seqi4e3:sig64:ssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssss5:token8:aoeusnth1:vl1:ad1:bi1eeee1:q3:put1:t2:aa1:y1:qe",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::Put),
+            query_args: Some(QueryArgs {
+                sender_id: b"abcdefghij0123456789".into(),
+                target: None,
+                info_hash: None,
+                implied_port: None,
+                port: None,
+                token: Some(b"aoeusnth".to_vec()),
+                want: None,
+                value: Some(RawValue {
+                    bytes: b"l1:ad1:bi1eee".to_vec(),
+                }),
+                key: Some([b'k'; 32]),
+                signature: Some([b's'; 64]),
+                seq: Some(4),
+                cas: Some(3),
+                salt: Some(b"foobar".to_vec()),
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(put_mutable.0, put_mutable.1);
+
+    let response = (
+        b"d1:rd2:id20:abcdefghij01234567891:k32:kkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkk3:seqi4e3:sig64:ssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssssss5:token8:aoeusnth1:vi-12ee1:t2:aa1:y1:re",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Response,
+            query_type: None,
+            query_args: None,
+            response: Some(Response {
+                sender_id: b"abcdefghij0123456789".into(),
+                nodes: None,
+                nodes6: None,
+                values: None,
+                token: Some(b"aoeusnth".to_vec()),
+                value: Some(RawValue {
+                    bytes: b"i-12e".to_vec(),
+                }),
+                key: Some([b'k'; 32]),
+                signature: Some([b's'; 64]),
+                seq: Some(4),
+                interval: None,
+                num: None,
+                samples: None,
+                seeds_filter: None,
+                peers_filter: None,
+                extra: Extra::new(),
+            }),
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(response.0, response.1);
+
+    assert_eq!(
+        RawValue::new(&"Hello World!").unwrap().bytes,
+        b"12:Hello World!"
+    );
+    assert!(RawValue {
+        bytes: b"i1ei2e".to_vec()
+    }
+    .to_bencode()
+    .is_err());
+}
+
+#[test]
+fn test_sample_infohashes() {
+    let query = (
+        b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q17:sample_infohashes1:t2:aa1:y1:qe",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::SampleInfohashes),
+            query_args: Some(QueryArgs {
+                sender_id: b"abcdefghij0123456789".into(),
+                target: Some(b"mnopqrstuvwxyz123456".into()),
+                info_hash: None,
+                implied_port: None,
+                port: None,
+                token: None,
+                want: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: None,
+                noseed: None,
+                extra: Extra::new(),
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(query.0, query.1);
+
+    let response = (
+        b"d1:rd2:id20:abcdefghij01234567898:intervali21600e5:nodes26:mnopqrstuvwxyz123456ABCDaa3:numi154e7:samples40:1111111111111111111122222222222222222222e1:t2:aa1:y1:re",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Response,
+            query_type: None,
+            query_args: None,
+            response: Some(Response {
+                sender_id: b"abcdefghij0123456789".into(),
+                nodes: Some(vec![(
+                    b"mnopqrstuvwxyz123456".into(),
+                    "65.66.67.68:24929".parse().unwrap(),
+                )
+                    .into()]),
+                nodes6: None,
+                values: None,
+                token: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                interval: Some(21600),
+                num: Some(154),
+                samples: Some(vec![
+                    b"11111111111111111111".into(),
+                    b"22222222222222222222".into(),
+                ]),
+                seeds_filter: None,
+                peers_filter: None,
+                extra: Extra::new(),
+            }),
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(response.0, response.1);
+
+    assert_invalid(
+        b"d1:rd2:id20:abcdefghij01234567897:samples21:111111111111111111112e1:t2:aa1:y1:re",
+    );
+}
+
+#[test]
+fn test_scrape() {
+    let get_peers = (
+        b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234566:noseedi0e6:scrapei1ee1:q9:get_peers1:t2:aa1:y1:qe",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::GetPeers),
+            query_args: Some(QueryArgs {
+                sender_id: b"abcdefghij0123456789".into(),
+                target: None,
+                info_hash: Some(b"mnopqrstuvwxyz123456".into()),
+                implied_port: None,
+                port: None,
+                token: None,
+                want: None,
+                value: None,
+                key: None,
+                signature: None,
+                seq: None,
+                cas: None,
+                salt: None,
+                scrape: Some(true),
+                noseed: Some(false),
+                extra: Extra::new(),
+            }),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(get_peers.0, get_peers.1);
+
+    let mut seeds_filter = ScrapeBloomFilter::new();
+    seeds_filter.insert("65.66.67.68".parse().unwrap());
+    let mut peers_filter = ScrapeBloomFilter::new();
+    peers_filter.insert("4142:4344:4546:4748:494a:4b4c:4d4e:4f50".parse().unwrap());
+    let mut bytes = b"d1:rd4:BFpe256:".to_vec();
+    bytes.extend_from_slice(&peers_filter.bytes[..]);
+    bytes.extend_from_slice(b"4:BFsd256:");
+    bytes.extend_from_slice(&seeds_filter.bytes[..]);
+    bytes.extend_from_slice(b"2:id20:abcdefghij01234567895:token8:aoeusnthe1:t2:aa1:y1:re");
+    let response = Message {
+        transaction_id: 24929u16.into(),
+        msg_type: MessageType::Response,
+        query_type: None,
+        query_args: None,
+        response: Some(Response {
+            sender_id: b"abcdefghij0123456789".into(),
+            nodes: None,
+            nodes6: None,
+            values: None,
+            token: Some(b"aoeusnth".to_vec()),
+            value: None,
+            key: None,
+            signature: None,
+            seq: None,
+            interval: None,
+            num: None,
+            samples: None,
+            seeds_filter: Some(seeds_filter),
+            peers_filter: Some(peers_filter),
+            extra: Extra::new(),
+        }),
+        error: None,
+        ip: None,
+        read_only: false,
+        version: None,
+        extra: Extra::new(),
+    };
+    ser_deser(&bytes, response);
+}
+
+#[test]
+fn test_ip() {
+    let response = (
+        b"d2:ip6:ABCDaa1:rd2:id20:abcdefghij0123456789e1:t2:aa1:y1:re",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Response,
+            query_type: None,
+            query_args: None,
+            response: Some(Response::new(b"abcdefghij0123456789".into())),
+            error: None,
+            ip: Some("65.66.67.68:24929".parse().unwrap()),
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(response.0, response.1);
+}
+
+#[test]
+fn test_read_only() {
+    let ping = (
+        b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping2:roi1e1:t2:aa1:y1:qe",
+        Message {
+            transaction_id: 24929u16.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::Ping),
+            query_args: Some(QueryArgs::new(b"abcdefghij0123456789".into())),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: true,
+            version: None,
+            extra: Extra::new(),
+        },
+    );
+    ser_deser(ping.0, ping.1);
+}
+
+#[test]
+fn test_version() {
+    let error = (
+        b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:v4:LT\x01\x021:y1:ee",
+        Message {
+            transaction_id: 24929u16.into(),
             msg_type: MessageType::Error,
             query_type: None,
             query_args: None,
@@ -142,8 +740,193 @@ fn test() {
             error: Some(Error {
                 code: 201,
                 message: "A Generic Error Ocurred".to_string(),
+                extra: Vec::new(),
             }),
+            ip: None,
+            read_only: false,
+            version: Some(b"LT\x01\x02".into()),
+            extra: Extra::new(),
         },
     );
     ser_deser(error.0, error.1);
+
+    let version = ClientVersion::from(b"LT\x01\x02");
+    assert_eq!(version.client_id(), Some(*b"LT"));
+    assert_eq!(version.version(), Some([1, 2]));
+    assert_eq!(version.client_name(), Some("libtorrent (Rasterbar)"));
+
+    let odd = ClientVersion::from(&b"XYZ"[..]);
+    assert_eq!(odd.version(), None);
+    assert_eq!(odd.client_name(), None);
+}
+
+#[test]
+fn test_transaction_id() {
+    for (bytes, t) in [
+        (
+            &b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t1:a1:y1:qe"[..],
+            &b"a"[..],
+        ),
+        (
+            b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t4:aaaa1:y1:qe",
+            b"aaaa",
+        ),
+        (
+            b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t8:abcdefgh1:y1:qe",
+            b"abcdefgh",
+        ),
+    ] {
+        let ping = Message {
+            transaction_id: t.into(),
+            msg_type: MessageType::Query,
+            query_type: Some(QueryType::Ping),
+            query_args: Some(QueryArgs::new(b"abcdefghij0123456789".into())),
+            response: None,
+            error: None,
+            ip: None,
+            read_only: false,
+            version: None,
+            extra: Extra::new(),
+        };
+        ser_deser(bytes, ping);
+    }
+
+    let t = TransactionId::from(24929u16);
+    assert_eq!(t.bytes, b"aa");
+    assert_eq!(u16::try_from(&t).unwrap(), 24929);
+    let e = u32::try_from(&t).unwrap_err();
+    assert!(matches!(e, crate::Error::InvalidLength { len: 2, .. }));
+    assert_eq!(e.path(), Some("t"));
+    let t = TransactionId::from(0x61616161u32);
+    assert_eq!(t.bytes, b"aaaa");
+    assert_eq!(u32::try_from(&t).unwrap(), 0x61616161);
+}
+
+#[test]
+fn test_lossless() {
+    let query =
+        &b"d1:ad2:id20:abcdefghij012345678911:unknown_argi5ee1:q4:ping1:t2:aa1:xl1:a1:be1:y1:qe"[..];
+    let m = Message::from_bencode_lossless(query).unwrap();
+    #[cfg(feature = "fast-decode")]
+    assert_eq!(crate::fast::decode_lossless(query).unwrap(), m);
+    let args = m.query_args.as_ref().unwrap();
+    assert_eq!(args.extra.len(), 1);
+    assert_eq!(args.extra[&b"unknown_arg"[..]].bytes, b"i5e");
+    assert_eq!(m.extra[&b"x"[..]].bytes, b"l1:a1:be");
+    assert_eq!(m.to_bencode().unwrap(), query);
+
+    let m = Message::from_bencode(query).unwrap();
+    #[cfg(feature = "fast-decode")]
+    assert_eq!(crate::fast::decode(query).unwrap(), m);
+    assert!(m.extra.is_empty());
+    assert!(m.query_args.as_ref().unwrap().extra.is_empty());
+    assert_eq!(
+        m.to_bencode().unwrap(),
+        b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe"
+    );
+
+    let response = &b"d2:aa0:1:rd2:id20:mnopqrstuvwxyz1234562:ipd3:fooi1eee1:t2:aa1:y1:re"[..];
+    let m = Message::from_bencode_lossless(response).unwrap();
+    #[cfg(feature = "fast-decode")]
+    assert_eq!(crate::fast::decode_lossless(response).unwrap(), m);
+    assert_eq!(m.extra[&b"aa"[..]].bytes, b"0:");
+    assert_eq!(
+        m.response.as_ref().unwrap().extra[&b"ip"[..]].bytes,
+        b"d3:fooi1ee"
+    );
+    assert_eq!(m.to_bencode().unwrap(), response);
+
+    // flags that a bool can't reproduce, and extra error list items
+    for bytes in [
+        &b"d1:ad2:id20:abcdefghij012345678912:implied_porti2e6:noseedi0e6:scrapei-1ee1:q4:ping2:roi0e1:t2:aa1:y1:qe"[..],
+        b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping2:roi7e1:t2:aa1:y1:qe",
+        b"d1:eli201e5:Errori1ed1:xi99999999999999999999eee1:t2:aa1:y1:ee",
+    ] {
+        let m = Message::from_bencode_lossless(bytes).unwrap();
+        #[cfg(feature = "fast-decode")]
+        assert_eq!(crate::fast::decode_lossless(bytes).unwrap(), m);
+        assert_eq!(m.to_bencode().unwrap(), bytes);
+    }
+    let m = Message::from_bencode_lossless(
+        b"d1:ad2:id20:abcdefghij012345678912:implied_porti2ee1:q4:ping2:roi0e1:t2:aa1:y1:qe",
+    )
+    .unwrap();
+    assert!(!m.read_only);
+    assert_eq!(m.extra[&b"ro"[..]].bytes, b"i0e");
+    let args = m.query_args.unwrap();
+    assert_eq!(args.implied_port, None);
+    assert_eq!(args.extra[&b"implied_port"[..]].bytes, b"i2e");
+
+    // a field that is set wins over the value kept for its key
+    let mut m = Message::from_bencode_lossless(
+        b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping2:roi0e1:t2:aa1:y1:qe",
+    )
+    .unwrap();
+    m.read_only = true;
+    assert_eq!(
+        m.to_bencode().unwrap(),
+        b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping2:roi1e1:t2:aa1:y1:qe"
+    );
+
+    // queries this crate doesn't know and values their field can't hold
+    let vote = &b"d1:ad2:id20:abcdefghij01234567894:votei5ee1:q4:vote1:t2:aa1:y1:qe"[..];
+    let port =
+        &b"d1:ad2:id20:abcdefghij01234567894:porti70000ee1:q13:announce_peer1:t2:aa1:y1:qe"[..];
+    let nodes = &b"d1:rd2:id20:mnopqrstuvwxyz1234565:nodes3:abce1:t2:aa1:y1:re"[..];
+    let no_id = &b"d1:ad6:targeti1ee1:q4:ping1:t2:aa1:y1:qe"[..];
+    for bytes in [vote, port, nodes, no_id] {
+        assert!(Message::from_bencode(bytes).is_err());
+        let m = Message::from_bencode_lossless(bytes).unwrap();
+        #[cfg(feature = "fast-decode")]
+        assert_eq!(crate::fast::decode_lossless(bytes).unwrap(), m);
+        assert_eq!(m.to_bencode().unwrap(), bytes);
+    }
+    let m = Message::from_bencode_lossless(vote).unwrap();
+    assert_eq!(m.query_type, None);
+    assert_eq!(m.extra[&b"q"[..]].bytes, b"4:vote");
+    assert_eq!(m.query_args.unwrap().extra[&b"vote"[..]].bytes, b"i5e");
+    let m = Message::from_bencode_lossless(port).unwrap();
+    let args = m.query_args.unwrap();
+    assert_eq!(args.port, None);
+    assert_eq!(args.extra[&b"port"[..]].bytes, b"i70000e");
+    let m = Message::from_bencode_lossless(no_id).unwrap();
+    assert_eq!(m.query_args, None);
+    assert_eq!(m.extra[&b"a"[..]].bytes, b"d6:targeti1ee");
+
+    // broken bencode is still an error
+    assert!(Message::from_bencode_lossless(b"d1:ad2:id3:abc1:q4:ping1:t2:aa1:y1:qe").is_err());
+}
+
+#[test]
+fn test_raw_value() {
+    let value = RawValue {
+        bytes: b"d1:ai99999999999999999999e1:bl0:ee".to_vec(),
+    };
+    assert_eq!(value.to_bencode().unwrap(), value.bytes);
+    for bytes in [&b"i1"[..], b"i1ei2e", b"d1:bi1e1:ai2ee", b""] {
+        let value = RawValue {
+            bytes: bytes.to_vec(),
+        };
+        assert!(value.to_bencode().is_err());
+    }
+}
+
+#[test]
+fn test_malformed() {
+    for bytes in [
+        &b""[..],
+        b"le",
+        b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:q",
+        // unsorted keys
+        b"d1:q4:ping1:ad2:id20:abcdefghij0123456789e1:t2:aa1:y1:qe",
+        // leading zero and negative zero
+        b"d1:ad2:id20:abcdefghij01234567894:porti06881ee1:q4:ping1:t2:aa1:y1:qe",
+        b"d1:ad2:id20:abcdefghij01234567894:porti-0ee1:q4:ping1:t2:aa1:y1:qe",
+        b"d1:ad2:id020:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+        // string running past the end
+        b"d1:ad2:id99:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+        b"d1:t2:aa1:y1:q1:xi1e1:xi2ee",
+    ] {
+        assert_invalid(bytes);
+    }
 }
@@ -0,0 +1,134 @@
+use std::net::SocketAddr;
+
+use crate::{
+    error::missing,
+    raw::{Hash, Node, QueryType, TransactionId},
+    version::ClientVersion,
+    Error, Response,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingResponse {
+    pub transaction_id: TransactionId,
+    pub sender_id: Hash,
+    pub ip: Option<SocketAddr>,
+    pub version: Option<ClientVersion>,
+}
+
+/// At least one of `nodes` and `nodes6` was sent, the other may be empty.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FindNodeResponse {
+    pub transaction_id: TransactionId,
+    pub sender_id: Hash,
+    pub nodes: Vec<Node>,
+    pub nodes6: Vec<Node>,
+    pub ip: Option<SocketAddr>,
+    pub version: Option<ClientVersion>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PeersOrNodes {
+    Peers(Vec<SocketAddr>),
+    Nodes { nodes: Vec<Node>, nodes6: Vec<Node> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetPeersResponse {
+    pub transaction_id: TransactionId,
+    pub sender_id: Hash,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::hex"))]
+    pub token: Vec<u8>,
+    /// Peers if the responder has any, closer nodes otherwise.
+    pub result: PeersOrNodes,
+    pub ip: Option<SocketAddr>,
+    pub version: Option<ClientVersion>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnnouncePeerResponse {
+    pub transaction_id: TransactionId,
+    pub sender_id: Hash,
+    pub ip: Option<SocketAddr>,
+    pub version: Option<ClientVersion>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TypedResponse {
+    Ping(PingResponse),
+    FindNode(FindNodeResponse),
+    GetPeers(GetPeersResponse),
+    AnnouncePeer(AnnouncePeerResponse),
+    /// Responses to queries without a typed form yet.
+    Other(Box<Response>),
+}
+
+fn nodes(r: &mut Response) -> Result<(Vec<Node>, Vec<Node>), Error> {
+    if r.nodes.is_none() && r.nodes6.is_none() {
+        return Err(missing!("r.nodes"));
+    }
+    Ok((
+        r.nodes.take().unwrap_or_default(),
+        r.nodes6.take().unwrap_or_default(),
+    ))
+}
+
+impl Response {
+    /// Interpret the response as the answer to a query of type `query`, which
+    /// the caller looks up by transaction ID. Checks the fields BEP 5 requires.
+    pub fn into_typed(mut self, query: QueryType) -> Result<TypedResponse, Error> {
+        Ok(match query {
+            QueryType::Ping => TypedResponse::Ping(PingResponse {
+                transaction_id: self.transaction_id,
+                sender_id: self.sender_id,
+                ip: self.ip,
+                version: self.version,
+            }),
+            QueryType::FindNone => {
+                let (nodes, nodes6) = nodes(&mut self)?;
+                TypedResponse::FindNode(FindNodeResponse {
+                    transaction_id: self.transaction_id,
+                    sender_id: self.sender_id,
+                    nodes,
+                    nodes6,
+                    ip: self.ip,
+                    version: self.version,
+                })
+            }
+            QueryType::GetPeers => {
+                let token = self.token.take().ok_or(missing!("r.token"))?;
+                let result = match self.values.take() {
+                    Some(values) => PeersOrNodes::Peers(values),
+                    None => {
+                        let (nodes, nodes6) = nodes(&mut self)?;
+                        PeersOrNodes::Nodes { nodes, nodes6 }
+                    }
+                };
+                TypedResponse::GetPeers(GetPeersResponse {
+                    transaction_id: self.transaction_id,
+                    sender_id: self.sender_id,
+                    token,
+                    result,
+                    ip: self.ip,
+                    version: self.version,
+                })
+            }
+            QueryType::AnnouncePeer => TypedResponse::AnnouncePeer(AnnouncePeerResponse {
+                transaction_id: self.transaction_id,
+                sender_id: self.sender_id,
+                ip: self.ip,
+                version: self.version,
+            }),
+            QueryType::Get | QueryType::Put | QueryType::SampleInfohashes => {
+                TypedResponse::Other(Box::new(self))
+            }
+        })
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    raw::{Node, QueryType},
+    response::{PeersOrNodes, TypedResponse},
+    Error, FindNode, Message, Ping, Response,
+};
+
+fn response(bytes: &[u8]) -> Response {
+    let Message::Response(r) = Message::decode(bytes).unwrap() else {
+        panic!("expected a response");
+    };
+    r
+}
+
+#[test]
+fn test_ping() {
+    let r = response(b"d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re");
+    let TypedResponse::Ping(p) = r.into_typed(QueryType::Ping).unwrap() else {
+        panic!("expected a ping response");
+    };
+    assert_eq!(p.transaction_id, 24929u16.into());
+    assert_eq!(p.sender_id, b"mnopqrstuvwxyz123456".into());
+}
+
+#[test]
+fn test_find_node() {
+    let bytes = b"d1:rd2:id20:0123456789abcdefghij5:nodes26:mnopqrstuvwxyz123456\x7f\x00\x00\x01\x1a\xe1e1:t2:aa1:y1:re";
+    let TypedResponse::FindNode(f) = response(bytes).into_typed(QueryType::FindNone).unwrap()
+    else {
+        panic!("expected a find_node response");
+    };
+    let node: Node = (
+        b"mnopqrstuvwxyz123456".into(),
+        "127.0.0.1:6881".parse().unwrap(),
+    )
+        .into();
+    assert_eq!(f.nodes, vec![node]);
+    assert!(f.nodes6.is_empty());
+
+    let r = response(b"d1:rd2:id20:0123456789abcdefghije1:t2:aa1:y1:re");
+    let e = r.into_typed(QueryType::FindNone).unwrap_err();
+    assert!(matches!(e, Error::MissingField { path } if path == "r.nodes"));
+}
+
+#[test]
+fn test_get_peers() {
+    let bytes = b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee1:t2:aa1:y1:re";
+    let TypedResponse::GetPeers(g) = response(bytes).into_typed(QueryType::GetPeers).unwrap()
+    else {
+        panic!("expected a get_peers response");
+    };
+    assert_eq!(g.token, b"aoeusnth");
+    assert_eq!(
+        g.result,
+        PeersOrNodes::Peers(vec![
+            "97.120.106.101:11893".parse().unwrap(),
+            "105.100.104.116:28269".parse().unwrap(),
+        ])
+    );
+
+    let bytes = b"d1:rd2:id20:abcdefghij01234567895:nodes0:5:token8:aoeusnthe1:t2:aa1:y1:re";
+    let TypedResponse::GetPeers(g) = response(bytes).into_typed(QueryType::GetPeers).unwrap()
+    else {
+        panic!("expected a get_peers response");
+    };
+    assert_eq!(
+        g.result,
+        PeersOrNodes::Nodes {
+            nodes: vec![],
+            nodes6: vec![]
+        }
+    );
+
+    let r = response(b"d1:rd2:id20:abcdefghij01234567896:valuesl6:axje.uee1:t2:aa1:y1:re");
+    let e = r.into_typed(QueryType::GetPeers).unwrap_err();
+    assert!(matches!(e, Error::MissingField { path } if path == "r.token"));
+
+    let r = response(b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnthe1:t2:aa1:y1:re");
+    let e = r.into_typed(QueryType::GetPeers).unwrap_err();
+    assert!(matches!(e, Error::MissingField { path } if path == "r.nodes"));
+}
+
+#[test]
+fn test_announce_peer() {
+    let r = response(b"d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re");
+    assert!(matches!(
+        r.clone().into_typed(QueryType::AnnouncePeer).unwrap(),
+        TypedResponse::AnnouncePeer(_)
+    ));
+    assert_eq!(
+        r.clone().into_typed(QueryType::Get).unwrap(),
+        TypedResponse::Other(Box::new(r))
+    );
+}
+
+#[test]
+fn test_reply() {
+    let ping = Ping::new(24929u16, b"abcdefghij0123456789");
+    assert_eq!(
+        ping.reply(b"mnopqrstuvwxyz123456").encode().unwrap(),
+        b"d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re"
+    );
+
+    let Message::GetPeers(get_peers) = Message::decode(
+        b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe",
+    )
+    .unwrap() else {
+        panic!("expected a get_peers query");
+    };
+    let peers = vec![
+        "97.120.106.101:11893".parse().unwrap(),
+        "105.100.104.116:28269".parse().unwrap(),
+    ];
+    assert_eq!(
+        get_peers
+            .reply_peers(b"abcdefghij0123456789", b"aoeusnth".to_vec(), peers)
+            .encode()
+            .unwrap(),
+        &b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee1:t2:aa1:y1:re"[..]
+    );
+
+    let v4: Node = (
+        b"mnopqrstuvwxyz123456".into(),
+        "127.0.0.1:6881".parse().unwrap(),
+    )
+        .into();
+    let v6: Node = (
+        b"abcdefghij0123456789".into(),
+        "[::1]:6881".parse().unwrap(),
+    )
+        .into();
+    let r = get_peers.reply_nodes(
+        b"abcdefghij0123456789",
+        b"aoeusnth".to_vec(),
+        vec![v4.clone(), v6.clone()],
+    );
+    assert_eq!(r.transaction_id, 24929u16.into());
+    assert_eq!(r.nodes, Some(vec![v4.clone()]));
+    assert_eq!(r.nodes6, Some(vec![v6.clone()]));
+    assert_eq!(r.token.as_deref(), Some(&b"aoeusnth"[..]));
+
+    let find_node = FindNode::new(24929u16, b"abcdefghij0123456789", b"mnopqrstuvwxyz123456");
+    let r = find_node.reply(b"0123456789abcdefghij", vec![v6.clone()]);
+    assert_eq!(r.nodes, None);
+    assert_eq!(r.nodes6, Some(vec![v6]));
+    let r = find_node.reply(b"0123456789abcdefghij", vec![]);
+    assert_eq!(r.nodes, Some(vec![]));
+    assert!(matches!(
+        r.into_typed(QueryType::FindNone).unwrap(),
+        TypedResponse::FindNode(_)
+    ));
+
+    let Message::AnnouncePeer(announce) = Message::decode(
+        b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
+    )
+    .unwrap() else {
+        panic!("expected an announce_peer query");
+    };
+    assert_eq!(
+        announce.reply(b"mnopqrstuvwxyz123456").encode().unwrap(),
+        b"d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re"
+    );
+}
@@ -0,0 +1,270 @@
+//! BEP 5 routing table.
+//!
+//! The table starts with a single bucket covering the whole ID space. When
+//! the bucket holding our own ID is full it is split in two, up to 160
+//! buckets. Other full buckets keep new nodes in a replacement cache until
+//! one of their nodes goes bad.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    raw::{Hash, Node},
+};
+
+/// Nodes per bucket.
+pub const K: usize = 8;
+/// Upper bound on the number of buckets, one per bit of the ID.
+pub const MAX_BUCKETS: usize = 160;
+/// A node is good if we heard from it within this interval.
+pub const GOOD_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Failed queries in a row after which a node is bad.
+pub const MAX_FAILURES: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeState {
+    Good,
+    /// Not heard from in [`GOOD_INTERVAL`], worth a ping.
+    Questionable,
+    /// Failed [`MAX_FAILURES`] queries in a row, replaced when possible.
+    Bad,
+}
+
+/// What [`RoutingTable::insert`] did with a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Insert {
+    Added,
+    /// The node was already in the table.
+    Updated,
+    /// Its bucket is full, the node went to the replacement cache.
+    Cached,
+    /// Our own ID.
+    Ignored,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    node: Node,
+    last_seen: Instant,
+    failures: u8,
+}
+
+impl Entry {
+    fn state(&self, now: Instant) -> NodeState {
+        if self.failures >= MAX_FAILURES {
+            NodeState::Bad
+        } else if now.saturating_duration_since(self.last_seen) < GOOD_INTERVAL {
+            NodeState::Good
+        } else {
+            NodeState::Questionable
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Bucket {
+    entries: Vec<Entry>,
+    /// Most recently seen last.
+    replacements: VecDeque<Entry>,
+}
+
+impl Bucket {
+    fn position(&self, id: &Hash) -> Option<usize> {
+        self.entries.iter().position(|e| &e.node.id == id)
+    }
+
+    fn cache(&mut self, entry: Entry) {
+        self.replacements.retain(|e| e.node.id != entry.node.id);
+        if self.replacements.len() == K {
+            self.replacements.pop_front();
+        }
+        self.replacements.push_back(entry);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RoutingTable<C = SystemClock> {
+    own_id: Hash,
+    /// Bucket `i` holds nodes sharing exactly `i` leading bits with our ID,
+    /// the last one also everything closer.
+    buckets: Vec<Bucket>,
+    clock: C,
+}
+
+impl RoutingTable {
+    pub fn new(own_id: Hash) -> Self {
+        Self::with_clock(own_id, SystemClock)
+    }
+}
+
+impl<C: Clock> RoutingTable<C> {
+    pub fn with_clock(own_id: Hash, clock: C) -> Self {
+        RoutingTable {
+            own_id,
+            buckets: vec![Bucket::default()],
+            clock,
+        }
+    }
+
+    pub fn own_id(&self) -> &Hash {
+        &self.own_id
+    }
+
+    /// Number of nodes in the table, not counting replacement caches.
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn bucket_index(&self, id: &Hash) -> usize {
+        self.own_id
+            .common_prefix_len(id)
+            .min(self.buckets.len() - 1)
+    }
+
+    fn entry(&self, id: &Hash) -> Option<&Entry> {
+        let bucket = &self.buckets[self.bucket_index(id)];
+        bucket.position(id).map(|i| &bucket.entries[i])
+    }
+
+    pub fn get(&self, id: &Hash) -> Option<&Node> {
+        self.entry(id).map(|e| &e.node)
+    }
+
+    pub fn state(&self, id: &Hash) -> Option<NodeState> {
+        let now = self.clock.now();
+        self.entry(id).map(|e| e.state(now))
+    }
+
+    /// Record that `node` responded to us or sent us a query. It becomes
+    /// good and takes its bucket's place of a bad node if there is one.
+    pub fn insert(&mut self, node: Node) -> Insert {
+        if node.id == self.own_id {
+            return Insert::Ignored;
+        }
+        let entry = Entry {
+            node,
+            last_seen: self.clock.now(),
+            failures: 0,
+        };
+        loop {
+            let index = self.bucket_index(&entry.node.id);
+            let can_split = index == self.buckets.len() - 1 && self.buckets.len() < MAX_BUCKETS;
+            let now = entry.last_seen;
+            let bucket = &mut self.buckets[index];
+
+            if let Some(i) = bucket.position(&entry.node.id) {
+                bucket.entries[i] = entry;
+                return Insert::Updated;
+            }
+            if bucket.entries.len() < K {
+                bucket.entries.push(entry);
+                return Insert::Added;
+            }
+            if let Some(i) = bucket
+                .entries
+                .iter()
+                .position(|e| e.state(now) == NodeState::Bad)
+            {
+                bucket.entries[i] = entry;
+                return Insert::Added;
+            }
+            if can_split {
+                self.split_last();
+                continue;
+            }
+            bucket.cache(entry);
+            return Insert::Cached;
+        }
+    }
+
+    /// Move the nodes that share more than `index` bits with our ID into a
+    /// new last bucket.
+    fn split_last(&mut self) {
+        let index = self.buckets.len() - 1;
+        let own_id = self.own_id.clone();
+        let closer = |e: &Entry| own_id.common_prefix_len(&e.node.id) > index;
+        let last = &mut self.buckets[index];
+        let mut new = Bucket::default();
+        let (near, far) = last.entries.drain(..).partition(closer);
+        new.entries = near;
+        last.entries = far;
+        let (near, far) = last.replacements.drain(..).partition(closer);
+        new.replacements = near;
+        last.replacements = far;
+        self.buckets.push(new);
+    }
+
+    /// Record a query to `id` that timed out. Once the node is bad it is
+    /// swapped for the most recently seen node of the replacement cache.
+    pub fn failed(&mut self, id: &Hash) {
+        let now = self.clock.now();
+        let index = self.bucket_index(id);
+        let bucket = &mut self.buckets[index];
+        let Some(i) = bucket.position(id) else {
+            bucket.replacements.retain(|e| &e.node.id != id);
+            return;
+        };
+        let entry = &mut bucket.entries[i];
+        entry.failures = entry.failures.saturating_add(1);
+        if entry.state(now) == NodeState::Bad {
+            if let Some(replacement) = bucket.replacements.pop_back() {
+                bucket.entries[i] = replacement;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: &Hash) -> Option<Node> {
+        let index = self.bucket_index(id);
+        let bucket = &mut self.buckets[index];
+        let i = bucket.position(id)?;
+        let entry = match bucket.replacements.pop_back() {
+            Some(replacement) => std::mem::replace(&mut bucket.entries[i], replacement),
+            None => bucket.entries.remove(i),
+        };
+        Some(entry.node)
+    }
+
+    /// Nodes we have not heard from in a while, to be pinged.
+    pub fn questionable(&self) -> Vec<Node> {
+        let now = self.clock.now();
+        self.buckets
+            .iter()
+            .flat_map(|b| &b.entries)
+            .filter(|e| e.state(now) == NodeState::Questionable)
+            .map(|e| e.node.clone())
+            .collect()
+    }
+
+    /// Up to `n` nodes closest to `target` by XOR distance, closest first,
+    /// leaving out bad nodes. Suitable for `nodes` in replies.
+    pub fn closest(&self, target: &Hash, n: usize) -> Vec<Node> {
+        let now = self.clock.now();
+        let mut nodes: Vec<_> = self
+            .buckets
+            .iter()
+            .flat_map(|b| &b.entries)
+            .filter(|e| e.state(now) != NodeState::Bad)
+            .map(|e| &e.node)
+            .collect();
+        nodes.sort_by_key(|node| target.distance(&node.id));
+        nodes.into_iter().take(n).cloned().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        self.buckets
+            .iter()
+            .flat_map(|b| &b.entries)
+            .map(|e| &e.node)
+    }
+}
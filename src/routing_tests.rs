@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use crate::{
+    clock_tests::MockClock,
+    raw::{Hash, Node},
+    routing::{Insert, NodeState, RoutingTable, GOOD_INTERVAL, K},
+};
+
+fn id(first: u8, last: u8) -> Hash {
+    let mut bytes = [0; 20];
+    bytes[0] = first;
+    bytes[19] = last;
+    Hash { bytes }
+}
+
+fn node(id: Hash) -> Node {
+    Node {
+        addr: ([127, 0, 0, 1], 6881 + id.bytes[19] as u16).into(),
+        id,
+    }
+}
+
+fn table() -> (RoutingTable<MockClock>, MockClock) {
+    let clock = MockClock::new();
+    (RoutingTable::with_clock(id(0, 0), clock.clone()), clock)
+}
+
+/// Fill the far half (first bit set) and force a split so that bucket 0 is
+/// no longer the one holding our ID.
+fn fill_far(table: &mut RoutingTable<MockClock>) {
+    for i in 0..K as u8 {
+        assert_eq!(table.insert(node(id(0x80, i))), Insert::Added);
+    }
+    assert_eq!(table.insert(node(id(0x40, 0))), Insert::Added);
+    assert_eq!(table.bucket_count(), 2);
+}
+
+#[test]
+fn test_insert() {
+    let (mut table, _) = table();
+    assert!(table.is_empty());
+    assert_eq!(table.insert(node(id(0, 0))), Insert::Ignored);
+
+    fill_far(&mut table);
+    assert_eq!(table.len(), K + 1);
+    assert_eq!(table.insert(node(id(0x80, 3))), Insert::Updated);
+    assert_eq!(table.insert(node(id(0xff, 0))), Insert::Cached);
+    assert_eq!(table.len(), K + 1);
+    assert!(table.get(&id(0xff, 0)).is_none());
+    assert_eq!(table.get(&id(0x80, 3)), Some(&node(id(0x80, 3))));
+}
+
+#[test]
+fn test_split() {
+    let (mut table, _) = table();
+    // one node per possible common prefix length with our ID
+    for bit in 0..160 {
+        let mut bytes = [0; 20];
+        bytes[bit / 8] = 0x80 >> (bit % 8);
+        assert_eq!(table.insert(node(Hash { bytes })), Insert::Added);
+    }
+    assert_eq!(table.len(), 160);
+    assert!(table.bucket_count() <= 160);
+
+    // the far bucket does not split any more
+    for i in 1..K as u8 + 1 {
+        table.insert(node(id(0x80, i)));
+    }
+    assert_eq!(table.iter().filter(|n| n.id.bytes[0] >= 0x80).count(), K);
+}
+
+#[test]
+fn test_states() {
+    let (mut table, clock) = table();
+    let a = id(0x80, 1);
+    table.insert(node(a.clone()));
+    assert_eq!(table.state(&a), Some(NodeState::Good));
+    assert!(table.questionable().is_empty());
+
+    clock.advance(GOOD_INTERVAL);
+    assert_eq!(table.state(&a), Some(NodeState::Questionable));
+    assert_eq!(table.questionable(), vec![node(a.clone())]);
+
+    table.insert(node(a.clone()));
+    assert_eq!(table.state(&a), Some(NodeState::Good));
+
+    table.failed(&a);
+    assert_eq!(table.state(&a), Some(NodeState::Good));
+    table.failed(&a);
+    assert_eq!(table.state(&a), Some(NodeState::Bad));
+    assert!(table.closest(&a, K).is_empty());
+
+    // hearing from it again makes it good
+    table.insert(node(a.clone()));
+    assert_eq!(table.state(&a), Some(NodeState::Good));
+}
+
+#[test]
+fn test_replacement() {
+    let (mut table, clock) = table();
+    fill_far(&mut table);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(table.insert(node(id(0x90, 20))), Insert::Cached);
+    assert_eq!(table.insert(node(id(0x90, 21))), Insert::Cached);
+
+    // the most recently seen replacement takes the place of a bad node
+    let bad = id(0x80, 0);
+    table.failed(&bad);
+    table.failed(&bad);
+    assert!(table.get(&bad).is_none());
+    assert!(table.get(&id(0x90, 21)).is_some());
+    assert_eq!(table.len(), K + 1);
+
+    assert_eq!(table.remove(&id(0x80, 1)), Some(node(id(0x80, 1))));
+    assert!(table.get(&id(0x90, 20)).is_some());
+    assert_eq!(table.len(), K + 1);
+
+    // with the cache empty a bad node stays until a new node shows up
+    let bad = id(0x80, 2);
+    table.failed(&bad);
+    table.failed(&bad);
+    assert_eq!(table.state(&bad), Some(NodeState::Bad));
+    assert_eq!(table.insert(node(id(0x90, 22))), Insert::Added);
+    assert!(table.get(&bad).is_none());
+}
+
+#[test]
+fn test_closest() {
+    let (mut table, _) = table();
+    for first in [0x01, 0x02, 0x10, 0x20, 0x80, 0x81, 0xc0] {
+        table.insert(node(id(first, first)));
+    }
+    let closest: Vec<_> = table
+        .closest(&id(0x83, 0), 3)
+        .into_iter()
+        .map(|n| n.id.bytes[0])
+        .collect();
+    assert_eq!(closest, vec![0x81, 0x80, 0xc0]);
+    assert_eq!(table.closest(&id(0, 0), 100).len(), 7);
+    assert_eq!(table.closest(&id(0, 0), 2)[0].id, id(0x01, 0x01));
+}
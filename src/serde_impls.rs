@@ -0,0 +1,107 @@
+//! Serde support: byte strings as hex, addresses as `ip:port` strings.
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::raw::{ClientVersion, Hash, RawValue, ScrapeBloomFilter, TransactionId};
+
+/// `#[serde(with = "hex")]` for `Vec<u8>` and `[u8; N]`.
+pub(crate) mod hex {
+    use super::*;
+
+    pub fn serialize<T: AsRef<[u8]> + ?Sized, S: Serializer>(
+        bytes: &T,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&::hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let bytes = ::hex::decode(String::deserialize(d)?).map_err(D::Error::custom)?;
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| D::Error::invalid_length(len, &"a fixed length string"))
+    }
+}
+
+/// [`hex`] for optional fields.
+pub(crate) mod hex_opt {
+    use super::*;
+
+    pub fn serialize<T: AsRef<[u8]>, S: Serializer>(
+        bytes: &Option<T>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        bytes.as_ref().map(::hex::encode).serialize(s)
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<Option<T>, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrap<T: TryFrom<Vec<u8>>>(#[serde(with = "hex")] T);
+
+        Ok(Option::<Wrap<T>>::deserialize(d)?.map(|w| w.0))
+    }
+}
+
+/// Unrecognised keys, hex encoded so that any key survives a round trip.
+pub(crate) mod extra {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::raw::Extra;
+
+    pub fn serialize<S: Serializer>(extra: &Extra, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(extra.iter().map(|(k, v)| (::hex::encode(k), v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Extra, D::Error> {
+        BTreeMap::<String, RawValue>::deserialize(d)?
+            .into_iter()
+            .map(|(k, v)| Ok((::hex::decode(k).map_err(D::Error::custom)?, v)))
+            .collect()
+    }
+}
+
+macro_rules! hex_impl {
+    ($t:ty, $bytes:ident => $from:expr) => {
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                hex::serialize(&self.bytes[..], s)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                let $bytes: Vec<u8> = hex::deserialize(d)?;
+                $from
+            }
+        }
+    };
+}
+
+hex_impl!(Hash, bytes => Ok(Hash {
+    bytes: bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| D::Error::invalid_length(b.len(), &"20 bytes"))?,
+}));
+hex_impl!(TransactionId, bytes => Ok(TransactionId { bytes }));
+hex_impl!(ClientVersion, bytes => Ok(ClientVersion { bytes }));
+hex_impl!(ScrapeBloomFilter, bytes => Ok(ScrapeBloomFilter {
+    bytes: Box::new(
+        bytes
+            .try_into()
+            .map_err(|b: Vec<u8>| D::Error::invalid_length(b.len(), &"256 bytes"))?,
+    ),
+}));
+// the exact encoding, BEP 44 signatures depend on it
+hex_impl!(RawValue, bytes => {
+    let value = RawValue { bytes };
+    value.check().map_err(D::Error::custom)?;
+    Ok(value)
+});
@@ -0,0 +1,93 @@
+use bendy::encoding::ToBencode;
+use serde_json::json;
+
+use crate::{borrowed_tests::PACKETS, raw, ErrorCode, Message};
+
+#[test]
+fn test_round_trip() {
+    for bytes in PACKETS {
+        let m = Message::decode(bytes).unwrap();
+        let s = serde_json::to_string(&m).unwrap();
+        let back: Message = serde_json::from_str(&s).unwrap();
+        assert_eq!(back, m);
+        assert_eq!(back.encode().unwrap(), *bytes);
+    }
+}
+
+#[test]
+fn test_json() {
+    let m = Message::decode(PACKETS[9]).unwrap();
+    let Message::Response(r) = &m else {
+        panic!("expected a response");
+    };
+    let v = serde_json::to_value(r).unwrap();
+    assert_eq!(v["transaction_id"], "6161");
+    assert_eq!(v["sender_id"], "6162636465666768696a30313233343536373839");
+    assert_eq!(v["token"], "616f6575736e7468");
+    assert_eq!(
+        v["values"],
+        json!([
+            "65.66.67.68:24929",
+            "[4142:4344:4546:4748:494a:4b4c:4d4e:4f50]:24929"
+        ])
+    );
+    assert_eq!(v["nodes"], json!(null));
+
+    let m = Message::decode(PACKETS[10]).unwrap();
+    let v = serde_json::to_value(&m).unwrap();
+    assert_eq!(
+        v["response"]["nodes"],
+        json!([{
+            "id": "6d6e6f707172737475767778797a313233343536",
+            "addr": "65.66.67.68:24929",
+        }])
+    );
+
+    let v = serde_json::to_value(Message::decode(PACKETS[0]).unwrap()).unwrap();
+    assert_eq!(v["ping"]["read_only"], false);
+
+    assert_eq!(serde_json::to_value(ErrorCode::Protocol).unwrap(), 203);
+    assert_eq!(
+        serde_json::from_value::<ErrorCode>(json!(302)).unwrap(),
+        ErrorCode::SequenceLessThanCurrent
+    );
+    assert!(serde_json::from_value::<Message>(json!({"ping": {
+        "transaction_id": "6161",
+        "sender_id": "6162",
+        "read_only": false,
+        "version": null,
+    }}))
+    .is_err());
+}
+
+#[test]
+fn test_raw() {
+    let bytes =
+        &b"d1:ad2:id20:abcdefghij012345678911:unknown_argi5ee1:q4:ping1:t2:aa1:xl1:a1:be1:y1:qe"[..];
+    let m = raw::Message::from_bencode_lossless(bytes).unwrap();
+    let v = serde_json::to_value(&m).unwrap();
+    assert_eq!(v["query_type"], "ping");
+    assert_eq!(v["msg_type"], "query");
+    assert_eq!(v["extra"], json!({"78": "6c313a61313a6265"}));
+    assert_eq!(
+        v["query_args"]["extra"],
+        json!({"756e6b6e6f776e5f617267": "693565"})
+    );
+
+    let back: raw::Message = serde_json::from_value(v).unwrap();
+    assert_eq!(back, m);
+    assert_eq!(back.to_bencode().unwrap(), bytes);
+
+    let m = raw::Message::from_bencode_lossless(PACKETS[1]).unwrap();
+    let v = serde_json::to_value(&m).unwrap();
+    assert_eq!(v["query_type"], "find_node");
+    assert_eq!(v["query_args"]["want"], json!(["n4", "n6"]));
+    assert!(v.get("extra").is_none());
+
+    // raw values have to be bencode
+    assert!(serde_json::from_value::<raw::RawValue>(json!("693565")).is_ok());
+    assert!(serde_json::from_value::<raw::RawValue>(json!("6935")).is_err());
+    let mut v = serde_json::to_value(&m).unwrap();
+    v["extra"] = json!({"78": "6c313a61"});
+    assert!(serde_json::from_value::<raw::Message>(v).is_err());
+}
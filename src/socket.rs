@@ -0,0 +1,235 @@
+//! KRPC over a tokio UDP socket.
+//!
+//! [`KrpcSocket::bind`] spawns a task that reads packets, hands responses to
+//! the queries waiting for them and passes incoming queries to [`Incoming`].
+//! Queries that fail to decode are answered with an error right away, up to
+//! a limit per second.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    raw::TransactionId,
+    transaction::TransactionManager,
+    udp::{is_per_packet, ErrorReplies},
+    Message, QueryError, Response,
+};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_RETRIES: u32 = 2;
+/// Incoming queries buffered before new ones are dropped.
+const INCOMING_CAPACITY: usize = 256;
+
+struct Inner {
+    socket: UdpSocket,
+    state: Mutex<State>,
+}
+
+struct State {
+    transactions: TransactionManager,
+    /// Keyed like the transactions, IDs are only unique per address.
+    waiting: HashMap<(SocketAddr, TransactionId), oneshot::Sender<Message>>,
+}
+
+impl Inner {
+    /// Nothing panics while the lock is held, but a poisoned lock must not
+    /// take every later query down with it.
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn send(&self, msg: &Message, addr: SocketAddr) -> Result<(), QueryError> {
+        let mut buf = Vec::new();
+        msg.encode_into(&mut buf)?;
+        self.socket.send_to(&buf, addr).await?;
+        Ok(())
+    }
+}
+
+pub struct KrpcSocket {
+    inner: Arc<Inner>,
+    task: JoinHandle<()>,
+    timeout: Duration,
+    retries: u32,
+}
+
+/// Queries sent to us, see [`KrpcSocket::bind`].
+pub struct Incoming {
+    rx: mpsc::Receiver<IncomingQuery>,
+}
+
+impl Incoming {
+    /// The next query, `None` once the socket is dropped.
+    pub async fn next(&mut self) -> Option<IncomingQuery> {
+        self.rx.recv().await
+    }
+}
+
+pub struct IncomingQuery {
+    pub message: Message,
+    pub from: SocketAddr,
+    inner: Arc<Inner>,
+}
+
+impl IncomingQuery {
+    /// Send `reply` back to the sender. Its transaction ID is set to the
+    /// query's.
+    pub async fn reply(&self, reply: impl Into<Message>) -> Result<(), QueryError> {
+        let mut reply = reply.into();
+        reply.set_transaction_id(self.message.transaction_id().clone());
+        self.inner.send(&reply, self.from).await
+    }
+}
+
+impl KrpcSocket {
+    /// Must be called from within a tokio runtime.
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<(Self, Incoming)> {
+        let inner = Arc::new(Inner {
+            socket: UdpSocket::bind(addr).await?,
+            state: Mutex::new(State {
+                transactions: TransactionManager::new(DEFAULT_TIMEOUT),
+                waiting: HashMap::new(),
+            }),
+        });
+        let (tx, rx) = mpsc::channel(INCOMING_CAPACITY);
+        let task = tokio::spawn(recv_loop(inner.clone(), tx));
+        let socket = KrpcSocket {
+            inner,
+            task,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+        };
+        Ok((socket, Incoming { rx }))
+    }
+
+    /// How long to wait for a response before retransmitting.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retransmits after the first attempt.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.socket.local_addr()
+    }
+
+    /// Send `query` to `addr` under a fresh transaction ID and wait for the
+    /// response. A KRPC error reply becomes [`QueryError::Remote`].
+    pub async fn query(
+        &self,
+        addr: SocketAddr,
+        query: impl Into<Message>,
+    ) -> Result<Response, QueryError> {
+        let mut msg = query.into();
+        let query_type = msg.query_type().ok_or(QueryError::NotAQuery)?;
+        let (tx, mut rx) = oneshot::channel();
+        let transaction_id = {
+            let mut state = self.inner.state();
+            let transaction_id = state.transactions.start_with_timeout(
+                addr,
+                query_type,
+                msg.query_target(),
+                self.timeout.saturating_mul(self.retries.saturating_add(1)),
+            )?;
+            state.waiting.insert((addr, transaction_id.clone()), tx);
+            transaction_id
+        };
+        // forgets the transaction however this future ends
+        let _pending = Pending {
+            inner: &self.inner,
+            addr,
+            transaction_id: transaction_id.clone(),
+        };
+        msg.set_transaction_id(transaction_id);
+        let mut buf = Vec::new();
+        msg.encode_into(&mut buf)?;
+
+        for _ in 0..=self.retries {
+            self.inner.socket.send_to(&buf, addr).await?;
+            match tokio::time::timeout(self.timeout, &mut rx).await {
+                Ok(Ok(Message::Response(response))) => return Ok(response),
+                Ok(Ok(Message::Error(error))) => return Err(QueryError::Remote(error)),
+                Ok(_) => break,
+                Err(_) => continue,
+            }
+        }
+        Err(QueryError::Timeout)
+    }
+}
+
+impl Drop for KrpcSocket {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Pending<'a> {
+    inner: &'a Inner,
+    addr: SocketAddr,
+    transaction_id: TransactionId,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        let mut state = self.inner.state();
+        state.transactions.cancel(self.addr, &self.transaction_id);
+        state
+            .waiting
+            .remove(&(self.addr, self.transaction_id.clone()));
+    }
+}
+
+async fn recv_loop(inner: Arc<Inner>, incoming: mpsc::Sender<IncomingQuery>) {
+    let mut buf = vec![0; 65536];
+    let mut error_replies = ErrorReplies::new(Instant::now());
+    loop {
+        let (len, from) = match inner.socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) if is_per_packet(&e) => continue,
+            Err(_) => break,
+        };
+        let msg = match Message::decode_lenient(&buf[..len]) {
+            Ok(msg) => msg,
+            Err(failure) => {
+                if let Some(reply) = error_replies.reply(&failure, Instant::now()) {
+                    let _ = inner.send(&reply.into(), from).await;
+                }
+                continue;
+            }
+        };
+        if msg.query_type().is_none() {
+            let mut state = inner.state();
+            // unsolicited and spoofed responses are dropped
+            if let Ok(t) = state.transactions.complete(from, &msg) {
+                if let Some(waiter) = state.waiting.remove(&(t.addr, t.transaction_id)) {
+                    let _ = waiter.send(msg);
+                }
+            }
+            continue;
+        }
+        // when the receiver is full or gone the query is dropped, like a lost packet
+        let _ = incoming.try_send(IncomingQuery {
+            message: msg,
+            from,
+            inner: inner.clone(),
+        });
+    }
+    // the socket is broken, waiting queries give up now rather than at their timeout
+    inner.state().waiting.clear();
+}
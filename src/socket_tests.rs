@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::{
+    raw::{Node, QueryType},
+    response::TypedResponse,
+    socket::{Incoming, KrpcSocket},
+    ErrorCode, ErrorMessage, FindNode, Message, Ping, QueryError,
+};
+
+const SERVER_ID: &[u8; 20] = b"mnopqrstuvwxyz123456";
+const CLIENT_ID: &[u8; 20] = b"abcdefghij0123456789";
+
+async fn bind() -> (KrpcSocket, Incoming) {
+    KrpcSocket::bind("127.0.0.1:0").await.unwrap()
+}
+
+/// Answers pings and find_node, and get_peers with an error.
+async fn serve(mut incoming: Incoming) {
+    while let Some(query) = incoming.next().await {
+        let reply: Message = match &query.message {
+            Message::Ping(p) => p.reply(SERVER_ID).into(),
+            Message::FindNode(f) => f
+                .reply(
+                    SERVER_ID,
+                    vec![Node {
+                        id: SERVER_ID.into(),
+                        addr: query.from,
+                    }],
+                )
+                .into(),
+            m => ErrorMessage::new(m.transaction_id().clone(), ErrorCode::Server, "go away").into(),
+        };
+        query.reply(reply).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_query() {
+    let (server, incoming) = bind().await;
+    let server_addr = server.local_addr().unwrap();
+    tokio::spawn(serve(incoming));
+    let (client, _) = bind().await;
+
+    let response = client
+        .query(server_addr, Ping::new(0u16, CLIENT_ID))
+        .await
+        .unwrap();
+    assert_eq!(response.sender_id, SERVER_ID.into());
+
+    let response = client
+        .query(server_addr, FindNode::new(0u16, CLIENT_ID, SERVER_ID))
+        .await
+        .unwrap();
+    let TypedResponse::FindNode(r) = response.into_typed(QueryType::FindNone).unwrap() else {
+        panic!("expected a find_node response");
+    };
+    assert_eq!(r.nodes[0].addr, client.local_addr().unwrap());
+
+    let error = client
+        .query(
+            server_addr,
+            crate::GetPeers::new(0u16, CLIENT_ID, SERVER_ID),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error, QueryError::Remote(e) if e.code == ErrorCode::Server));
+
+    // concurrent queries are told apart by transaction ID
+    let (a, b) = tokio::join!(
+        client.query(server_addr, Ping::new(0u16, CLIENT_ID)),
+        client.query(server_addr, Ping::new(0u16, CLIENT_ID)),
+    );
+    assert!(a.is_ok() && b.is_ok());
+
+    let reply = ErrorMessage::generic(0u16, "");
+    assert!(matches!(
+        client.query(server_addr, reply).await,
+        Err(QueryError::NotAQuery)
+    ));
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let (server, mut incoming) = bind().await;
+    let server_addr = server.local_addr().unwrap();
+    let (client, _) = bind().await;
+    let client = client
+        .with_timeout(Duration::from_millis(50))
+        .with_retries(2);
+
+    let error = client
+        .query(server_addr, Ping::new(0u16, CLIENT_ID))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, QueryError::Timeout));
+
+    // the same packet went out three times
+    let mut seen = Vec::new();
+    for _ in 0..3 {
+        let query = incoming.next().await.unwrap();
+        seen.push(query.message.transaction_id().clone());
+    }
+    assert!(seen.windows(2).all(|w| w[0] == w[1]));
+
+    // and no more
+    let more = incoming.next();
+    assert!(tokio::time::timeout(Duration::from_millis(10), more)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_huge_retries() {
+    let (server, incoming) = bind().await;
+    let server_addr = server.local_addr().unwrap();
+    tokio::spawn(serve(incoming));
+    let (client, _) = bind().await;
+    let client = client.with_timeout(Duration::MAX).with_retries(u32::MAX);
+
+    let response = client
+        .query(server_addr, Ping::new(0u16, CLIENT_ID))
+        .await
+        .unwrap();
+    assert_eq!(response.sender_id, SERVER_ID.into());
+}
+
+#[tokio::test]
+async fn test_malformed_query() {
+    let (server, _incoming) = bind().await;
+    let server_addr = server.local_addr().unwrap();
+    let raw = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    raw.send_to(b"d1:ad2:id3:abce1:q4:ping1:t2:aa1:y1:qe", server_addr)
+        .await
+        .unwrap();
+    let mut buf = [0; 1500];
+    let (len, _) = raw.recv_from(&mut buf).await.unwrap();
+    let Message::Error(e) = Message::decode(&buf[..len]).unwrap() else {
+        panic!("expected an error reply");
+    };
+    assert_eq!(e.code, ErrorCode::Protocol);
+    assert_eq!(e.transaction_id, b"aa".into());
+
+    // responses nobody asked for are ignored
+    let response = Ping::new(b"zz", CLIENT_ID).reply(SERVER_ID);
+    let mut bytes = Vec::new();
+    response.encode_into(&mut bytes).unwrap();
+    raw.send_to(&bytes, server_addr).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), raw.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,233 @@
+//! Matching responses to the queries we sent, without any runtime.
+//!
+//! ```
+//! # use std::time::Duration;
+//! # use krpc_message::{raw::QueryType, transaction::TransactionManager, Message, Ping};
+//! let mut transactions = TransactionManager::new(Duration::from_secs(5));
+//! let addr = "127.0.0.1:6881".parse().unwrap();
+//! let t = transactions.start(addr, QueryType::Ping, None).unwrap();
+//! let ping = Ping::new(t.clone(), b"abcdefghij0123456789");
+//! // ... send `ping`, and later on receiving a packet from `addr`:
+//! let reply = Message::Response(ping.reply(b"mnopqrstuvwxyz123456"));
+//! let query = transactions.complete(addr, &reply).unwrap();
+//! assert_eq!(query.query, QueryType::Ping);
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{self, Clock, SystemClock},
+    raw::{Hash, QueryType, TransactionId},
+    Message,
+};
+
+/// An outstanding query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub transaction_id: TransactionId,
+    pub addr: SocketAddr,
+    pub query: QueryType,
+    /// `target` or `info_hash` of the query.
+    pub target: Option<Hash>,
+    pub deadline: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatchError {
+    /// The message is a query.
+    NotAResponse,
+    /// No outstanding query to the sender has this transaction ID. This
+    /// covers responses spoofed from another address, whose query stays
+    /// outstanding.
+    Unsolicited,
+    /// The response came after the deadline, the query is dropped.
+    TimedOut(Box<Transaction>),
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAResponse => write!(f, "not a response"),
+            Self::Unsolicited => write!(f, "unsolicited response"),
+            Self::TimedOut(t) => write!(f, "response to {:?} after the deadline", t.query),
+        }
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+/// All 65536 transaction IDs are in use for queries to `addr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoIdsLeft {
+    pub addr: SocketAddr,
+}
+
+impl fmt::Display for NoIdsLeft {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no transaction IDs left for {}", self.addr)
+    }
+}
+
+impl std::error::Error for NoIdsLeft {}
+
+/// Number of two byte transaction IDs.
+const ID_SPACE: usize = 1 << 16;
+/// Random IDs tried before scanning for a free one.
+const RANDOM_TRIES: usize = 8;
+
+/// Hands out transaction IDs and remembers the queries they belong to.
+///
+/// IDs are random two byte strings, unique among the outstanding queries to
+/// the same address, so a response can only be matched by someone who saw
+/// the query.
+#[derive(Debug)]
+pub struct TransactionManager<C = SystemClock> {
+    pending: HashMap<SocketAddr, HashMap<TransactionId, Transaction>>,
+    len: usize,
+    timeout: Duration,
+    clock: C,
+}
+
+impl TransactionManager {
+    pub fn new(timeout: Duration) -> Self {
+        Self::with_clock(timeout, SystemClock)
+    }
+}
+
+impl<C: Clock> TransactionManager<C> {
+    pub fn with_clock(timeout: Duration, clock: C) -> Self {
+        TransactionManager {
+            pending: HashMap::new(),
+            len: 0,
+            timeout,
+            clock,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Register a query to `addr` and return the transaction ID to send it
+    /// with.
+    pub fn start(
+        &mut self,
+        addr: SocketAddr,
+        query: QueryType,
+        target: Option<Hash>,
+    ) -> Result<TransactionId, NoIdsLeft> {
+        self.start_with_timeout(addr, query, target, self.timeout)
+    }
+
+    /// Like [`start`](Self::start), with its own timeout, e.g. to cover
+    /// retransmits.
+    pub fn start_with_timeout(
+        &mut self,
+        addr: SocketAddr,
+        query: QueryType,
+        target: Option<Hash>,
+        timeout: Duration,
+    ) -> Result<TransactionId, NoIdsLeft> {
+        let transaction_id = self.free_id(addr)?;
+        let transaction = Transaction {
+            transaction_id: transaction_id.clone(),
+            addr,
+            query,
+            target,
+            deadline: clock::deadline(self.clock.now(), timeout),
+        };
+        self.pending
+            .entry(addr)
+            .or_default()
+            .insert(transaction_id.clone(), transaction);
+        self.len += 1;
+        Ok(transaction_id)
+    }
+
+    /// A few random picks, which almost always hit a free ID, then a scan
+    /// from a random point so a busy address doesn't slow down.
+    fn free_id(&self, addr: SocketAddr) -> Result<TransactionId, NoIdsLeft> {
+        let Some(pending) = self.pending.get(&addr) else {
+            return Ok(rand::random::<u16>().into());
+        };
+        if pending.len() >= ID_SPACE {
+            return Err(NoIdsLeft { addr });
+        }
+        let free = |id: &u16| !pending.contains_key(&id.to_be_bytes()[..]);
+        let start = rand::random::<u16>();
+        (0..RANDOM_TRIES)
+            .map(|_| rand::random::<u16>())
+            .find(free)
+            .or_else(|| (0..=u16::MAX).map(|i| start.wrapping_add(i)).find(free))
+            .map(TransactionId::from)
+            .ok_or(NoIdsLeft { addr })
+    }
+
+    pub fn get(&self, addr: SocketAddr, transaction_id: &TransactionId) -> Option<&Transaction> {
+        self.pending.get(&addr)?.get(transaction_id)
+    }
+
+    /// Forget a query, e.g. to retransmit it under a new ID.
+    pub fn cancel(
+        &mut self,
+        addr: SocketAddr,
+        transaction_id: &TransactionId,
+    ) -> Option<Transaction> {
+        let pending = self.pending.get_mut(&addr)?;
+        let transaction = pending.remove(transaction_id)?;
+        if pending.is_empty() {
+            self.pending.remove(&addr);
+        }
+        self.len -= 1;
+        Some(transaction)
+    }
+
+    /// Match a response or error received from `from` to its query, which
+    /// is then no longer outstanding.
+    pub fn complete(&mut self, from: SocketAddr, msg: &Message) -> Result<Transaction, MatchError> {
+        if !matches!(msg, Message::Response(_) | Message::Error(_)) {
+            return Err(MatchError::NotAResponse);
+        }
+        let transaction = self
+            .cancel(from, msg.transaction_id())
+            .ok_or(MatchError::Unsolicited)?;
+        if transaction.deadline <= self.clock.now() {
+            return Err(MatchError::TimedOut(Box::new(transaction)));
+        }
+        Ok(transaction)
+    }
+
+    /// Remove and return the queries whose deadline has passed.
+    pub fn expire(&mut self) -> Vec<Transaction> {
+        let now = self.clock.now();
+        let expired: Vec<_> = self
+            .pending
+            .values()
+            .flat_map(HashMap::values)
+            .filter(|t| t.deadline <= now)
+            .map(|t| (t.addr, t.transaction_id.clone()))
+            .collect();
+        expired
+            .iter()
+            .filter_map(|(addr, id)| self.cancel(*addr, id))
+            .collect()
+    }
+
+    /// The earliest deadline, for the receive timeout of an event loop.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .flat_map(HashMap::values)
+            .map(|t| t.deadline)
+            .min()
+    }
+}
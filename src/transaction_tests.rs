@@ -0,0 +1,128 @@
+use std::{collections::HashSet, net::SocketAddr, time::Duration};
+
+use crate::{
+    clock_tests::MockClock,
+    raw::QueryType,
+    transaction::{MatchError, NoIdsLeft, TransactionManager},
+    ErrorMessage, FindNode, Message, Ping,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const ID: &[u8; 20] = b"abcdefghij0123456789";
+const OTHER_ID: &[u8; 20] = b"mnopqrstuvwxyz123456";
+
+fn manager() -> (TransactionManager<MockClock>, MockClock) {
+    let clock = MockClock::new();
+    (
+        TransactionManager::with_clock(TIMEOUT, clock.clone()),
+        clock,
+    )
+}
+
+fn addr(port: u16) -> SocketAddr {
+    ([127, 0, 0, 1], port).into()
+}
+
+#[test]
+fn test_complete() {
+    let (mut tm, _) = manager();
+    let t = tm
+        .start(addr(1), QueryType::FindNone, Some(OTHER_ID.into()))
+        .unwrap();
+    assert_eq!(t.len(), 2);
+    assert_eq!(tm.len(), 1);
+
+    let query = FindNode::new(t.clone(), ID, OTHER_ID);
+    let reply = Message::Response(query.reply(OTHER_ID, vec![]));
+    assert_eq!(
+        tm.complete(addr(1), &Message::FindNode(query)),
+        Err(MatchError::NotAResponse)
+    );
+
+    let transaction = tm.complete(addr(1), &reply).unwrap();
+    assert_eq!(transaction.transaction_id, t);
+    assert_eq!(transaction.query, QueryType::FindNone);
+    assert_eq!(transaction.target, Some(OTHER_ID.into()));
+    assert!(tm.is_empty());
+
+    // a second copy is no longer expected
+    assert_eq!(tm.complete(addr(1), &reply), Err(MatchError::Unsolicited));
+
+    let t = tm.start(addr(1), QueryType::Ping, None).unwrap();
+    let error = Message::Error(ErrorMessage::generic(t, "nope"));
+    assert_eq!(tm.complete(addr(1), &error).unwrap().query, QueryType::Ping);
+}
+
+#[test]
+fn test_spoofed() {
+    let (mut tm, _) = manager();
+    let t = tm.start(addr(1), QueryType::Ping, None).unwrap();
+    let reply = Message::Response(Ping::new(t.clone(), ID).reply(OTHER_ID));
+    assert_eq!(tm.complete(addr(2), &reply), Err(MatchError::Unsolicited));
+    // the real response still gets through
+    assert!(tm.complete(addr(1), &reply).is_ok());
+}
+
+#[test]
+fn test_expire() {
+    let (mut tm, clock) = manager();
+    assert_eq!(tm.next_deadline(), None);
+    let old = tm.start(addr(1), QueryType::Ping, None).unwrap();
+    let deadline = tm.next_deadline().unwrap();
+    clock.advance(Duration::from_secs(3));
+    let new = tm.start(addr(2), QueryType::Ping, None).unwrap();
+    assert_eq!(tm.next_deadline(), Some(deadline));
+
+    clock.advance(Duration::from_secs(2));
+    let expired = tm.expire();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].transaction_id, old);
+    assert!(tm.get(addr(1), &old).is_none());
+    assert!(tm.get(addr(2), &new).is_some());
+
+    // late response before `expire` ran
+    clock.advance(Duration::from_secs(4));
+    let reply = Message::Response(Ping::new(new.clone(), ID).reply(OTHER_ID));
+    assert!(matches!(
+        tm.complete(addr(2), &reply),
+        Err(MatchError::TimedOut(t)) if t.transaction_id == new
+    ));
+    assert!(tm.is_empty());
+}
+
+#[test]
+fn test_unique() {
+    let (mut tm, _) = manager();
+    let ids: HashSet<_> = (0..10_000)
+        .map(|_| tm.start(addr(1), QueryType::Ping, None).unwrap())
+        .collect();
+    assert_eq!(ids.len(), 10_000);
+    assert_eq!(tm.len(), 10_000);
+    let t = ids.iter().next().unwrap();
+    assert!(tm.cancel(addr(1), t).is_some());
+    assert!(tm.cancel(addr(1), t).is_none());
+}
+
+#[test]
+fn test_exhausted() {
+    let (mut tm, _) = manager();
+    let ids: HashSet<_> = (0..1 << 16)
+        .map(|_| tm.start(addr(1), QueryType::Ping, None).unwrap())
+        .collect();
+    assert_eq!(ids.len(), 1 << 16);
+    assert_eq!(
+        tm.start(addr(1), QueryType::Ping, None),
+        Err(NoIdsLeft { addr: addr(1) })
+    );
+
+    // IDs only have to be unique per address
+    let t = tm.start(addr(2), QueryType::Ping, None).unwrap();
+    assert!(tm.get(addr(1), &t).is_some());
+    let reply = Message::Response(Ping::new(t.clone(), ID).reply(OTHER_ID));
+    assert!(tm.complete(addr(2), &reply).is_ok());
+    assert!(tm.get(addr(1), &t).is_some());
+
+    // a freed ID can be handed out again
+    assert!(tm.cancel(addr(1), &t).is_some());
+    assert_eq!(tm.start(addr(1), QueryType::Ping, None), Ok(t));
+}
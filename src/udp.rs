@@ -0,0 +1,53 @@
+//! Helpers shared by the blocking and the tokio socket.
+
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::{raw::MessageType, DecodeFailure, ErrorMessage};
+
+/// Error replies to undecodable queries sent per second at most, so spoofed
+/// packets can't turn the socket into a reflector.
+pub(crate) const ERROR_REPLIES_PER_SECOND: u32 = 100;
+
+/// Errors such as ICMP port unreachable only concern one packet.
+pub(crate) fn is_per_packet(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Decides which undecodable packets get an error reply.
+pub(crate) struct ErrorReplies {
+    window: Instant,
+    sent: u32,
+}
+
+impl ErrorReplies {
+    pub(crate) fn new(now: Instant) -> Self {
+        ErrorReplies {
+            window: now,
+            sent: 0,
+        }
+    }
+
+    /// The reply to `failure`, if it was a query and the limit for the
+    /// current second isn't reached.
+    pub(crate) fn reply(&mut self, failure: &DecodeFailure, now: Instant) -> Option<ErrorMessage> {
+        if failure.msg_type != Some(MessageType::Query) {
+            return None;
+        }
+        let reply = failure.reply()?;
+        if now.saturating_duration_since(self.window) >= Duration::from_secs(1) {
+            self.window = now;
+            self.sent = 0;
+        }
+        if self.sent >= ERROR_REPLIES_PER_SECOND {
+            return None;
+        }
+        self.sent += 1;
+        Some(reply)
+    }
+}
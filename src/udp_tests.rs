@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    udp::{ErrorReplies, ERROR_REPLIES_PER_SECOND},
+    DecodeFailure, Message,
+};
+
+fn failure(bytes: &[u8]) -> DecodeFailure {
+    *Message::decode_lenient(bytes).unwrap_err()
+}
+
+#[test]
+fn test_error_replies() {
+    let now = Instant::now();
+    let mut replies = ErrorReplies::new(now);
+
+    // only queries are answered
+    let query = failure(b"d1:ad2:id3:abce1:q4:ping1:t2:aa1:y1:qe");
+    let response = failure(b"d1:rd2:id3:abce1:t2:aa1:y1:re");
+    let no_type = failure(b"d1:ad2:id3:abce1:q4:ping1:t2:aae");
+    let unknown_type = failure(b"d1:ad2:id3:abce1:q4:ping1:t2:aa1:y1:xe");
+    for f in [&response, &no_type, &unknown_type] {
+        assert!(replies.reply(f, now).is_none());
+    }
+
+    for _ in 0..ERROR_REPLIES_PER_SECOND {
+        assert!(replies.reply(&query, now).is_some());
+    }
+    let later = now + Duration::from_millis(999);
+    assert!(replies.reply(&query, later).is_none());
+    let later = now + Duration::from_secs(1);
+    assert!(replies.reply(&query, later).is_some());
+}
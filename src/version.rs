@@ -0,0 +1,93 @@
+use std::fmt;
+
+use bendy::{
+    decoding::{FromBencode, Object},
+    encoding::{SingleItemEncoder, ToBencode},
+};
+
+/// Two-letter client prefixes seen in the `v` key.
+pub const KNOWN_CLIENTS: &[(&[u8; 2], &str)] = &[
+    (b"AZ", "Vuze"),
+    (b"BC", "BitComet"),
+    (b"BT", "BitTorrent"),
+    (b"DE", "Deluge"),
+    (b"GR", "GetRight"),
+    (b"KT", "KTorrent"),
+    (b"LT", "libtorrent (Rasterbar)"),
+    (b"lt", "libTorrent (rakshasa)"),
+    (b"MO", "MonoTorrent"),
+    (b"qB", "qBittorrent"),
+    (b"TR", "Transmission"),
+    (b"UM", "uTorrent Mac"),
+    (b"UT", "uTorrent"),
+    (b"UW", "uTorrent Web"),
+    (b"XL", "Xunlei"),
+];
+
+/// Client identifier from the top-level `v` key, usually a two-letter
+/// client code followed by two version bytes, e.g. `LT\x01\x02`.
+/// Other lengths are kept as is.
+#[derive(PartialEq, Eq, Clone, Hash)]
+pub struct ClientVersion {
+    pub bytes: Vec<u8>,
+}
+
+impl ClientVersion {
+    pub fn client_id(&self) -> Option<[u8; 2]> {
+        self.bytes.get(0..2)?.try_into().ok()
+    }
+
+    pub fn version(&self) -> Option<[u8; 2]> {
+        if self.bytes.len() != 4 {
+            return None;
+        }
+        self.bytes[2..4].try_into().ok()
+    }
+
+    pub fn client_name(&self) -> Option<&'static str> {
+        let id = self.client_id()?;
+        KNOWN_CLIENTS
+            .iter()
+            .find(|(prefix, _)| **prefix == id)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl From<&[u8; 4]> for ClientVersion {
+    fn from(bytes: &[u8; 4]) -> Self {
+        ClientVersion {
+            bytes: bytes.to_vec(),
+        }
+    }
+}
+
+impl From<&[u8]> for ClientVersion {
+    fn from(bytes: &[u8]) -> Self {
+        ClientVersion {
+            bytes: bytes.to_vec(),
+        }
+    }
+}
+
+impl fmt::Debug for ClientVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.bytes {
+            write!(f, "{}", std::ascii::escape_default(*c))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromBencode for ClientVersion {
+    const EXPECTED_RECURSION_DEPTH: usize = 0;
+    fn decode_bencode_object(object: Object) -> Result<Self, bendy::decoding::Error> {
+        Ok(object.try_into_bytes()?.into())
+    }
+}
+
+impl ToBencode for ClientVersion {
+    const MAX_DEPTH: usize = 0;
+    fn encode(&self, encoder: SingleItemEncoder) -> Result<(), bendy::encoding::Error> {
+        encoder.emit_bytes(&self.bytes)
+    }
+}
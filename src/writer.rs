@@ -0,0 +1,498 @@
+//! Bencode written straight into a caller's buffer, keys in sorted order.
+
+use std::net::SocketAddr;
+
+use bytes::BufMut;
+
+use crate::{
+    error::{invalid_length, ResultExt},
+    raw::{Node, QueryType, TransactionId, Want},
+    version::ClientVersion,
+    AnnouncePeer, Error, ErrorMessage, FindNode, Get, GetPeers, Message, Ping, Put, Response,
+    SampleInfohashes,
+};
+
+/// Where the writers below put their bytes: the caller's buffer, or a
+/// counter to size the message first.
+trait Sink {
+    fn put_slice(&mut self, bytes: &[u8]);
+
+    fn put_u8(&mut self, b: u8) {
+        self.put_slice(&[b]);
+    }
+
+    fn put_u16(&mut self, n: u16) {
+        self.put_slice(&n.to_be_bytes());
+    }
+}
+
+impl<B: BufMut> Sink for B {
+    fn put_slice(&mut self, bytes: &[u8]) {
+        BufMut::put_slice(self, bytes);
+    }
+}
+
+/// Counts the bytes instead of writing them.
+struct Len(usize);
+
+impl Sink for Len {
+    fn put_slice(&mut self, bytes: &[u8]) {
+        self.0 += bytes.len();
+    }
+}
+
+trait Write {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error>;
+}
+
+fn encode_into<T: Write, B: BufMut>(msg: &T, buf: &mut B) -> Result<(), Error> {
+    let mut len = Len(0);
+    msg.write(&mut len)?;
+    let remaining = buf.remaining_mut();
+    if len.0 > remaining {
+        return Err(Error::BufferTooSmall {
+            needed: len.0,
+            remaining,
+        });
+    }
+    msg.write(buf)
+}
+
+macro_rules! impl_encode_into {
+    ($($t:ty),*) => {
+        $(
+            impl $t {
+                /// Write the message to `buf` without allocating. Nothing is
+                /// written if it fails, e.g. because `buf` is too short.
+                pub fn encode_into<B: BufMut>(&self, buf: &mut B) -> Result<(), Error> {
+                    encode_into(self, buf)
+                }
+            }
+        )*
+    };
+}
+
+impl_encode_into!(
+    Ping,
+    FindNode,
+    GetPeers,
+    AnnouncePeer,
+    Get,
+    Put,
+    SampleInfohashes,
+    ErrorMessage,
+    Response,
+    Message
+);
+
+fn put_decimal<S: Sink>(buf: &mut S, mut n: u64) {
+    let mut digits = [0u8; 20];
+    let mut pos = digits.len();
+    loop {
+        pos -= 1;
+        digits[pos] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    buf.put_slice(&digits[pos..]);
+}
+
+fn put_int<S: Sink>(buf: &mut S, i: i64) {
+    buf.put_u8(b'i');
+    if i < 0 {
+        buf.put_u8(b'-');
+    }
+    put_decimal(buf, i.unsigned_abs());
+    buf.put_u8(b'e');
+}
+
+fn put_bytes<S: Sink>(buf: &mut S, bytes: &[u8]) {
+    put_decimal(buf, bytes.len() as u64);
+    buf.put_u8(b':');
+    buf.put_slice(bytes);
+}
+
+fn put_addr<S: Sink>(buf: &mut S, addr: &SocketAddr) {
+    match addr {
+        SocketAddr::V4(a) => {
+            buf.put_slice(b"6:");
+            buf.put_slice(&a.ip().octets());
+        }
+        SocketAddr::V6(a) => {
+            buf.put_slice(b"18:");
+            buf.put_slice(&a.ip().octets());
+        }
+    }
+    buf.put_u16(addr.port());
+}
+
+fn check_nodes(nodes: Option<&Vec<Node>>, len: usize) -> Result<(), Error> {
+    for node in nodes.into_iter().flatten() {
+        if node.compact_len() != len {
+            return Err(invalid_length!(node.compact_len()));
+        }
+    }
+    Ok(())
+}
+
+fn put_nodes<S: Sink>(buf: &mut S, nodes: &[Node]) {
+    let len: usize = nodes.iter().map(Node::compact_len).sum();
+    put_decimal(buf, len as u64);
+    buf.put_u8(b':');
+    for node in nodes {
+        buf.put_slice(&node.id.bytes);
+        match node.addr {
+            SocketAddr::V4(a) => buf.put_slice(&a.ip().octets()),
+            SocketAddr::V6(a) => buf.put_slice(&a.ip().octets()),
+        }
+        buf.put_u16(node.addr.port());
+    }
+}
+
+fn put_want<S: Sink>(buf: &mut S, want: &[Want]) {
+    buf.put_u8(b'l');
+    for w in want {
+        buf.put_slice(match w {
+            Want::N4 => b"2:n4",
+            Want::N6 => b"2:n6",
+        });
+    }
+    buf.put_u8(b'e');
+}
+
+/// Top-level keys around the `a` dict, whose contents `args` writes.
+fn put_query<S: Sink>(
+    buf: &mut S,
+    transaction_id: &TransactionId,
+    query: QueryType,
+    read_only: bool,
+    version: Option<&ClientVersion>,
+    args: impl FnOnce(&mut S),
+) {
+    buf.put_slice(b"d1:ad");
+    args(buf);
+    buf.put_slice(b"e1:q");
+    put_bytes(buf, query.name());
+    if read_only {
+        buf.put_slice(b"2:roi1e");
+    }
+    put_tail(buf, transaction_id, version, b'q');
+}
+
+/// The `t`, `v` and `y` keys that close every message.
+fn put_tail<S: Sink>(
+    buf: &mut S,
+    transaction_id: &TransactionId,
+    version: Option<&ClientVersion>,
+    msg_type: u8,
+) {
+    buf.put_slice(b"1:t");
+    put_bytes(buf, &transaction_id.bytes);
+    if let Some(version) = version {
+        buf.put_slice(b"1:v");
+        put_bytes(buf, &version.bytes);
+    }
+    buf.put_slice(b"1:y1:");
+    buf.put_u8(msg_type);
+    buf.put_u8(b'e');
+}
+
+impl Write for Ping {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        put_query(
+            buf,
+            &self.transaction_id,
+            QueryType::Ping,
+            self.read_only,
+            self.version.as_ref(),
+            |buf| {
+                buf.put_slice(b"2:id");
+                put_bytes(buf, &self.sender_id.bytes);
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Write for FindNode {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        put_query(
+            buf,
+            &self.transaction_id,
+            QueryType::FindNone,
+            self.read_only,
+            self.version.as_ref(),
+            |buf| {
+                buf.put_slice(b"2:id");
+                put_bytes(buf, &self.sender_id.bytes);
+                buf.put_slice(b"6:target");
+                put_bytes(buf, &self.target.bytes);
+                if let Some(want) = &self.want {
+                    buf.put_slice(b"4:want");
+                    put_want(buf, want);
+                }
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Write for GetPeers {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        put_query(
+            buf,
+            &self.transaction_id,
+            QueryType::GetPeers,
+            self.read_only,
+            self.version.as_ref(),
+            |buf| {
+                buf.put_slice(b"2:id");
+                put_bytes(buf, &self.sender_id.bytes);
+                buf.put_slice(b"9:info_hash");
+                put_bytes(buf, &self.info_hash.bytes);
+                if let Some(noseed) = self.noseed {
+                    buf.put_slice(b"6:noseed");
+                    put_int(buf, noseed as i64);
+                }
+                if let Some(scrape) = self.scrape {
+                    buf.put_slice(b"6:scrape");
+                    put_int(buf, scrape as i64);
+                }
+                if let Some(want) = &self.want {
+                    buf.put_slice(b"4:want");
+                    put_want(buf, want);
+                }
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Write for AnnouncePeer {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        put_query(
+            buf,
+            &self.transaction_id,
+            QueryType::AnnouncePeer,
+            self.read_only,
+            self.version.as_ref(),
+            |buf| {
+                buf.put_slice(b"2:id");
+                put_bytes(buf, &self.sender_id.bytes);
+                if let Some(implied_port) = self.implied_port {
+                    buf.put_slice(b"12:implied_port");
+                    put_int(buf, implied_port as i64);
+                }
+                buf.put_slice(b"9:info_hash");
+                put_bytes(buf, &self.info_hash.bytes);
+                buf.put_slice(b"4:port");
+                put_int(buf, self.port.into());
+                buf.put_slice(b"5:token");
+                put_bytes(buf, &self.token);
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Write for Get {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        put_query(
+            buf,
+            &self.transaction_id,
+            QueryType::Get,
+            self.read_only,
+            self.version.as_ref(),
+            |buf| {
+                buf.put_slice(b"2:id");
+                put_bytes(buf, &self.sender_id.bytes);
+                if let Some(seq) = self.seq {
+                    buf.put_slice(b"3:seq");
+                    put_int(buf, seq);
+                }
+                buf.put_slice(b"6:target");
+                put_bytes(buf, &self.target.bytes);
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Write for Put {
+    /// `v` is written as its stored bytes, once checked to be bencode.
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        self.value.check().context("a.v")?;
+        put_query(
+            buf,
+            &self.transaction_id,
+            QueryType::Put,
+            self.read_only,
+            self.version.as_ref(),
+            |buf| {
+                if let Some(cas) = self.cas {
+                    buf.put_slice(b"3:cas");
+                    put_int(buf, cas);
+                }
+                buf.put_slice(b"2:id");
+                put_bytes(buf, &self.sender_id.bytes);
+                if let Some(key) = &self.key {
+                    buf.put_slice(b"1:k");
+                    put_bytes(buf, key);
+                }
+                if let Some(salt) = &self.salt {
+                    buf.put_slice(b"4:salt");
+                    put_bytes(buf, salt);
+                }
+                if let Some(seq) = self.seq {
+                    buf.put_slice(b"3:seq");
+                    put_int(buf, seq);
+                }
+                if let Some(signature) = &self.signature {
+                    buf.put_slice(b"3:sig");
+                    put_bytes(buf, signature);
+                }
+                buf.put_slice(b"5:token");
+                put_bytes(buf, &self.token);
+                buf.put_slice(b"1:v");
+                buf.put_slice(&self.value.bytes);
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Write for SampleInfohashes {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        put_query(
+            buf,
+            &self.transaction_id,
+            QueryType::SampleInfohashes,
+            self.read_only,
+            self.version.as_ref(),
+            |buf| {
+                buf.put_slice(b"2:id");
+                put_bytes(buf, &self.sender_id.bytes);
+                buf.put_slice(b"6:target");
+                put_bytes(buf, &self.target.bytes);
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Write for ErrorMessage {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        buf.put_slice(b"d1:eli");
+        let code: i64 = self.code.into();
+        if code < 0 {
+            buf.put_u8(b'-');
+        }
+        put_decimal(buf, code.unsigned_abs());
+        buf.put_u8(b'e');
+        put_bytes(buf, self.message.as_bytes());
+        buf.put_u8(b'e');
+        put_tail(buf, &self.transaction_id, self.version.as_ref(), b'e');
+        Ok(())
+    }
+}
+
+impl Write for Response {
+    /// Fails if a node's address family does not match its list, or `v`
+    /// isn't bencode.
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        check_nodes(self.nodes.as_ref(), 26).context("r.nodes")?;
+        check_nodes(self.nodes6.as_ref(), 38).context("r.nodes6")?;
+        if let Some(value) = &self.value {
+            value.check().context("r.v")?;
+        }
+
+        buf.put_u8(b'd');
+        if let Some(ip) = &self.ip {
+            buf.put_slice(b"2:ip");
+            put_addr(buf, ip);
+        }
+        buf.put_slice(b"1:rd");
+        if let Some(peers_filter) = &self.peers_filter {
+            buf.put_slice(b"4:BFpe");
+            put_bytes(buf, &peers_filter.bytes[..]);
+        }
+        if let Some(seeds_filter) = &self.seeds_filter {
+            buf.put_slice(b"4:BFsd");
+            put_bytes(buf, &seeds_filter.bytes[..]);
+        }
+        buf.put_slice(b"2:id");
+        put_bytes(buf, &self.sender_id.bytes);
+        if let Some(interval) = self.interval {
+            buf.put_slice(b"8:interval");
+            put_int(buf, interval);
+        }
+        if let Some(key) = &self.key {
+            buf.put_slice(b"1:k");
+            put_bytes(buf, key);
+        }
+        if let Some(nodes) = &self.nodes {
+            buf.put_slice(b"5:nodes");
+            put_nodes(buf, nodes);
+        }
+        if let Some(nodes6) = &self.nodes6 {
+            buf.put_slice(b"6:nodes6");
+            put_nodes(buf, nodes6);
+        }
+        if let Some(num) = self.num {
+            buf.put_slice(b"3:num");
+            put_int(buf, num);
+        }
+        if let Some(samples) = &self.samples {
+            buf.put_slice(b"7:samples");
+            put_decimal(buf, samples.len() as u64 * 20);
+            buf.put_u8(b':');
+            for sample in samples {
+                buf.put_slice(&sample.bytes);
+            }
+        }
+        if let Some(seq) = self.seq {
+            buf.put_slice(b"3:seq");
+            put_int(buf, seq);
+        }
+        if let Some(signature) = &self.signature {
+            buf.put_slice(b"3:sig");
+            put_bytes(buf, signature);
+        }
+        if let Some(token) = &self.token {
+            buf.put_slice(b"5:token");
+            put_bytes(buf, token);
+        }
+        if let Some(value) = &self.value {
+            buf.put_slice(b"1:v");
+            buf.put_slice(&value.bytes);
+        }
+        if let Some(values) = &self.values {
+            buf.put_slice(b"6:valuesl");
+            for addr in values {
+                put_addr(buf, addr);
+            }
+            buf.put_u8(b'e');
+        }
+        buf.put_u8(b'e');
+        put_tail(buf, &self.transaction_id, self.version.as_ref(), b'r');
+        Ok(())
+    }
+}
+
+impl Write for Message {
+    fn write<S: Sink>(&self, buf: &mut S) -> Result<(), Error> {
+        match self {
+            Self::Ping(p) => p.write(buf),
+            Self::FindNode(f) => f.write(buf),
+            Self::GetPeers(g) => g.write(buf),
+            Self::AnnouncePeer(a) => a.write(buf),
+            Self::Get(g) => g.write(buf),
+            Self::Put(p) => p.write(buf),
+            Self::SampleInfohashes(s) => s.write(buf),
+            Self::Response(r) => r.write(buf),
+            Self::Error(e) => e.write(buf),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{
+    borrowed_tests::PACKETS,
+    raw::{Node, RawValue},
+    Error, Message, Put, Response,
+};
+
+#[test]
+fn test_encode_into() {
+    for bytes in PACKETS {
+        let m = Message::decode(bytes).unwrap();
+        let mut buf = Vec::new();
+        m.encode_into(&mut buf).unwrap();
+        assert_eq!(buf, *bytes);
+        assert_eq!(buf, m.clone().encode().unwrap());
+    }
+}
+
+#[test]
+fn test_encode_into_slice() {
+    let m = Message::decode(PACKETS[3]).unwrap();
+    let mut buf = [0u8; 1500];
+    let mut rest = &mut buf[..];
+    m.encode_into(&mut rest).unwrap();
+    let len = 1500 - rest.len();
+    assert_eq!(&buf[..len], PACKETS[3]);
+
+    // one byte short fails without writing anything
+    let mut buf = vec![0u8; len - 1];
+    let mut rest = &mut buf[..];
+    let e = m.encode_into(&mut rest).unwrap_err();
+    assert!(
+        matches!(e, Error::BufferTooSmall { needed, remaining } if needed == len && remaining == len - 1)
+    );
+    assert_eq!(rest.len(), len - 1);
+}
+
+#[test]
+fn test_encode_into_invalid_value() {
+    let value = RawValue {
+        bytes: b"i1ei2e".to_vec(),
+    };
+    let put = Put::new(
+        b"aa",
+        b"abcdefghij0123456789",
+        b"token".to_vec(),
+        value.clone(),
+    );
+    let mut buf = Vec::new();
+    let e = put.encode_into(&mut buf).unwrap_err();
+    assert_eq!(e.path(), Some("a.v"));
+    assert!(buf.is_empty());
+    assert!(put.encode().is_err());
+
+    let mut r = Response::new(b"aa", b"abcdefghij0123456789");
+    r.value = Some(value);
+    let e = r.encode_into(&mut buf).unwrap_err();
+    assert_eq!(e.path(), Some("r.v"));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_encode_into_wrong_family() {
+    let mut r = Response::new(b"aa", b"abcdefghij0123456789");
+    r.nodes6 = Some(vec![Node {
+        id: b"mnopqrstuvwxyz123456".into(),
+        addr: "1.2.3.4:5".parse().unwrap(),
+    }]);
+    let mut buf = Vec::new();
+    let e = r.encode_into(&mut buf).unwrap_err();
+    assert!(matches!(&e, Error::InvalidLength { path, len: 26 } if path == "r.nodes6"));
+    assert!(buf.is_empty());
+}